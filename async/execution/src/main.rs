@@ -7,17 +7,18 @@
 
 use {
     futures::{
-        future::{BoxFuture, FutureExt},
+        future::{BoxFuture, Either, FutureExt},
         task::{waker_ref, ArcWake},
     },
     std::{
         future::Future,
         pin::Pin,
-        sync::mpsc::{sync_channel, Receiver, SyncSender},
+        sync::atomic::{AtomicBool, Ordering},
+        sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError},
         sync::{Arc, Mutex},
         task::{Context, Poll, Waker},
-        thread,
-        time::Duration,
+        thread::{self, JoinHandle},
+        time::{Duration, Instant},
     },
 };
 
@@ -37,17 +38,57 @@ struct SharedState {
     waker: Option<Waker>,
 }
 
+/// Handle to stop the waiting thread of a `TimerFuture` before its time has elapsed
+pub struct TimerHandle {
+    /// Set to tell the waiting thread to give up
+    cancelled: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl TimerHandle {
+    /// Stops the waiting thread and returns once it has exited.
+    /// A cancelled timer never completes, so its future stays pending.
+    pub fn cancel(self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        // Wake up the thread if it is parked, so it sees the flag
+        self.thread.thread().unpark();
+        self.thread.join().unwrap();
+    }
+}
+
 impl TimerFuture {
     pub fn new(duration: Duration) -> Self {
+        // Dropping the handle lets the timer run to completion
+        Self::with_handle(duration).0
+    }
+
+    /// Creates a timer along with a handle which can cancel it
+    pub fn with_handle(duration: Duration) -> (Self, TimerHandle) {
         let shared_state = Arc::new(Mutex::new(SharedState {
             completed: false,
             waker: None,
         }));
+        let cancelled = Arc::new(AtomicBool::new(false));
 
         // Spawn the new thread
         let thread_shared_state = shared_state.clone();
-        thread::spawn(move || {
-            thread::sleep(duration);
+        let thread_cancelled = cancelled.clone();
+        let thread = thread::spawn(move || {
+            // Instead of sleeping the whole duration, the thread parks itself
+            // so that a cancellation can wake it up early
+            let deadline = Instant::now() + duration;
+            loop {
+                if thread_cancelled.load(Ordering::SeqCst) {
+                    return;
+                }
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                // It may also wake up spuriously, hence the loop
+                thread::park_timeout(deadline - now);
+            }
+
             let mut shared_state = thread_shared_state.lock().unwrap();
             // Signal that the timer has completed and wake up the last
             // task on which the future was polled, if one exists.
@@ -57,7 +98,8 @@ impl TimerFuture {
             }
         });
 
-        TimerFuture { shared_state }
+        let handle = TimerHandle { cancelled, thread };
+        (TimerFuture { shared_state }, handle)
     }
 }
 
@@ -87,6 +129,68 @@ impl Future for TimerFuture {
     }
 }
 
+/// Timer which completes once every period, like a ticking clock
+pub struct Interval {
+    period: Duration,
+    /// When the next tick is due
+    next: Instant,
+}
+
+impl Interval {
+    /// Creates an interval whose first tick completes after one period
+    pub fn new(period: Duration) -> Self {
+        Self {
+            period,
+            next: Instant::now() + period,
+        }
+    }
+
+    /// Returns a future which completes at the next tick.
+    /// Ticks are due at fixed times, so waiting late for one
+    /// makes the following ones shorter instead of drifting.
+    pub fn tick(&mut self) -> TimerFuture {
+        let remaining = self.next.saturating_duration_since(Instant::now());
+        self.next += self.period;
+        TimerFuture::new(remaining)
+    }
+}
+
+/// Future which completes with the output of whichever of two futures completes first
+pub struct Race<A, B> {
+    a: A,
+    b: B,
+}
+
+/// Races two futures, the loser is dropped along with the race without completing.
+/// Futures which are not `Unpin` can take part once pinned with `Box::pin`.
+pub fn race<A, B>(a: A, b: B) -> Race<A, B>
+where
+    A: Future + Unpin,
+    B: Future + Unpin,
+{
+    Race { a, b }
+}
+
+impl<A, B> Future for Race<A, B>
+where
+    A: Future + Unpin,
+    B: Future + Unpin,
+{
+    type Output = Either<A::Output, B::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Both futures get the waker of the task,
+        // so either of them can wake it up when it is ready
+        if let Poll::Ready(output) = Pin::new(&mut self.a).poll(cx) {
+            return Poll::Ready(Either::Left(output));
+        }
+        if let Poll::Ready(output) = Pin::new(&mut self.b).poll(cx) {
+            return Poll::Ready(Either::Right(output));
+        }
+        Poll::Pending
+    }
+}
+
 // Executors run the futures, by calling poll whenever they make progress.
 // It works by sending tasks to run over a channel.
 // The executor again pull events off of the channel and run them.
@@ -127,20 +231,48 @@ impl Executor {
     }
 }
 
+/// Errors which prevent a future from being spawned
+#[derive(Debug, PartialEq, Eq)]
+enum SpawnError {
+    /// The task queue already holds as many tasks as its capacity
+    QueueFull,
+    /// The executor has been dropped, so no task would ever run
+    Closed,
+}
+
+impl std::fmt::Display for SpawnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpawnError::QueueFull => write!(f, "Too many tasks queued"),
+            SpawnError::Closed => write!(f, "Executor is closed"),
+        }
+    }
+}
+
+impl std::error::Error for SpawnError {}
+
 /// `Spawner` spawns new futures onto the task channel.
+///
+/// The channel is bounded, which applies backpressure: when the executor
+/// falls behind and the queue is full, `spawn` fails with `SpawnError::QueueFull`
+/// instead of blocking, so the caller can retry later or drop the future.
+/// Tasks waking themselves up do block until there is room in the queue.
 #[derive(Clone)]
 struct Spawner {
     task_sender: SyncSender<Arc<Task>>,
 }
 
 impl Spawner {
-    fn spawn(&self, future: impl Future<Output = ()> + 'static + Send) {
+    fn spawn(&self, future: impl Future<Output = ()> + 'static + Send) -> Result<(), SpawnError> {
         let future = future.boxed();
         let task = Arc::new(Task {
             future: Mutex::new(Some(future)),
             task_sender: self.task_sender.clone(),
         });
-        self.task_sender.send(task).expect("too many tasks queued");
+        self.task_sender.try_send(task).map_err(|err| match err {
+            TrySendError::Full(_) => SpawnError::QueueFull,
+            TrySendError::Disconnected(_) => SpawnError::Closed,
+        })
     }
 }
 
@@ -172,10 +304,13 @@ impl ArcWake for Task {
 
 fn new_executor_and_spawner() -> (Executor, Spawner) {
     // Maximum number of tasks to allow queueing in the channel at once.
-    // This is just to make `sync_channel` happy, and wouldn't be present in
-    // a real executor.
     const MAX_QUEUED_TASKS: usize = 10_000;
-    let (task_sender, ready_queue) = sync_channel(MAX_QUEUED_TASKS);
+    new_executor_and_spawner_with_capacity(MAX_QUEUED_TASKS)
+}
+
+/// Creates an executor whose queue holds at most `capacity` tasks at once
+fn new_executor_and_spawner_with_capacity(capacity: usize) -> (Executor, Spawner) {
+    let (task_sender, ready_queue) = sync_channel(capacity);
     (Executor { ready_queue }, Spawner { task_sender })
 }
 
@@ -183,18 +318,97 @@ fn main() {
     let (executor, spawner) = new_executor_and_spawner();
 
     // Spawn a task to print before and after waiting on a timer.
-    spawner.spawn(async {
-        println!("howdy!");
-        // Wait for our timer future to complete after two seconds.
-        TimerFuture::new(Duration::new(2, 0)).await;
-        println!("done!");
-    });
+    spawner
+        .spawn(async {
+            println!("howdy!");
+            // Wait for our timer future to complete after two seconds.
+            TimerFuture::new(Duration::new(2, 0)).await;
+            println!("done!");
+        })
+        .expect("Failed to spawn task");
+
+    // Spawn another task which runs along the first one
+    spawner
+        .spawn(async {
+            let mut interval = Interval::new(Duration::from_secs(1));
+            for i in 1..=3 {
+                interval.tick().await;
+                println!("tick {}", i);
+            }
+        })
+        .expect("Failed to spawn task");
+
+    // Race a short timer against a long one
+    spawner
+        .spawn(async {
+            let short = TimerFuture::new(Duration::from_millis(500));
+            let long = TimerFuture::new(Duration::from_millis(1500));
+            match race(short, long).await {
+                Either::Left(()) => println!("the short timer won"),
+                Either::Right(()) => println!("the long timer won"),
+            }
+        })
+        .expect("Failed to spawn task");
 
     // Drop the spawner so that our executor knows it is finished and won't
     // receive more incoming tasks to run.
     drop(spawner);
 
     // Run the executor until the task queue is empty.
-    // This will print "howdy!", pause, and then print "done!".
+    // This will print "howdy!", a tick every second, and "done!" after two seconds.
     executor.run();
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cancel_timer() {
+        let (timer, handle) = TimerFuture::with_handle(Duration::from_secs(5));
+
+        let start = Instant::now();
+        handle.cancel();
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(!timer.shared_state.lock().unwrap().completed);
+    }
+
+    #[test]
+    fn interval_ticks() {
+        let period = Duration::from_millis(20);
+        let start = Instant::now();
+        let mut interval = Interval::new(period);
+
+        futures::executor::block_on(async {
+            for _ in 0..5 {
+                interval.tick().await;
+            }
+        });
+
+        // Ticks are due at 20, 40, 60, 80, and 100 milliseconds, and none completes early
+        assert!(start.elapsed() >= period * 5);
+    }
+
+    #[test]
+    fn race_timers() {
+        let short = TimerFuture::new(Duration::from_millis(10));
+        let (long, handle) = TimerFuture::with_handle(Duration::from_secs(5));
+
+        let winner = futures::executor::block_on(race(long, short));
+        assert!(matches!(winner, Either::Right(())));
+
+        handle.cancel();
+    }
+
+    #[test]
+    fn spawn_into_full_queue() {
+        let (executor, spawner) = new_executor_and_spawner_with_capacity(2);
+
+        assert_eq!(spawner.spawn(async {}), Ok(()));
+        assert_eq!(spawner.spawn(async {}), Ok(()));
+        assert_eq!(spawner.spawn(async {}), Err(SpawnError::QueueFull));
+
+        drop(executor);
+        assert_eq!(spawner.spawn(async {}), Err(SpawnError::Closed));
+    }
+}
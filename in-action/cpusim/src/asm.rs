@@ -0,0 +1,118 @@
+// Assembler for a minimal CHIP-8 mnemonic syntax
+//
+// One instruction per line, operands separated by commas,
+// comments start with a semicolon:
+//
+//     LD V0, 0x05   ; V0 = 5
+//     ADD V0, V1
+//     HALT
+
+/// Parses a register operand such as `V0` or `VF`
+fn register(operand: &str) -> Result<u16, String> {
+    let index = operand
+        .strip_prefix('V')
+        .or_else(|| operand.strip_prefix('v'))
+        .ok_or_else(|| format!("Expected a register, found `{}`", operand))?;
+
+    // Registers go from V0 to VF
+    match u16::from_str_radix(index, 16) {
+        Ok(index) if index < 16 => Ok(index),
+        _ => Err(format!("Invalid register `{}`", operand)),
+    }
+}
+
+/// Parses a hex (`0x` prefixed) or decimal immediate no greater than max
+fn immediate(operand: &str, max: u16) -> Result<u16, String> {
+    let value = match operand
+        .strip_prefix("0x")
+        .or_else(|| operand.strip_prefix("0X"))
+    {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => operand.parse(),
+    }
+    .map_err(|_| format!("Invalid number `{}`", operand))?;
+
+    if value > max {
+        return Err(format!("Value `{}` does not fit in {:#x}", operand, max));
+    }
+    Ok(value)
+}
+
+/// Encodes a single instruction into its opcode
+fn encode(mnemonic: &str, operands: &[&str]) -> Result<u16, String> {
+    let opcode = match (mnemonic.to_uppercase().as_str(), operands) {
+        ("HALT", []) => 0x0000,
+        ("RET", []) => 0x00EE,
+        ("JMP", [addr]) => 0x1000 | immediate(addr, 0x0FFF)?,
+        ("CALL", [addr]) => 0x2000 | immediate(addr, 0x0FFF)?,
+        ("LD", [x, kk]) => 0x6000 | register(x)? << 8 | immediate(kk, 0xFF)?,
+        ("ADD", [x, y]) => 0x8004 | register(x)? << 8 | register(y)? << 4,
+        (mnemonic, operands) => {
+            return Err(format!(
+                "Unknown instruction `{}` with {} operands",
+                mnemonic,
+                operands.len()
+            ))
+        }
+    };
+    Ok(opcode)
+}
+
+/// Assembles source code into big-endian opcode bytes
+pub fn assemble(src: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = vec![];
+
+    for (number, line) in src.lines().enumerate() {
+        // Strip comments
+        let line = line.split(';').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, operands) = match line.split_once(char::is_whitespace) {
+            Some((mnemonic, operands)) => (mnemonic, operands.split(',').map(str::trim).collect()),
+            None => (line, vec![]),
+        };
+
+        let opcode =
+            encode(mnemonic, &operands).map_err(|err| format!("Line {}: {}", number + 1, err))?;
+        bytes.extend_from_slice(&opcode.to_be_bytes());
+    }
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn assemble_program() {
+        let src = "
+            ; Add two numbers within a function
+            LD V0, 0x05
+            LD V1, 10     ; decimal
+            CALL 0x208
+            HALT
+            add v0, v1
+            RET
+            JMP 0x200
+        ";
+
+        let expected = vec![
+            0x60, 0x05, 0x61, 0x0A, 0x22, 0x08, 0x00, 0x00, 0x80, 0x14, 0x00, 0xEE, 0x12, 0x00,
+        ];
+        assert_eq!(assemble(src), Ok(expected));
+    }
+
+    #[test]
+    fn assemble_errors() {
+        assert!(assemble("ADD V0").unwrap_err().starts_with("Line 1"));
+        assert!(assemble("HALT\nLD VG, 1")
+            .unwrap_err()
+            .starts_with("Line 2"));
+        assert!(assemble("LD V0, 0x100").is_err());
+        assert!(assemble("JMP 4096").is_err());
+        assert!(assemble("NOP").is_err());
+    }
+}
@@ -6,6 +6,164 @@
 // Operands must be moved to registers for the operation to function.
 //
 // Opcode = number that maps an operation
+
+mod asm;
+
+/// Errors which stop the CPU from executing a program
+#[derive(Debug, PartialEq, Eq)]
+enum Chip8Error {
+    /// The opcode does not map to any implemented operation
+    UnknownOpcode(u16),
+    /// The program counter points past the end of memory
+    PcOutOfBounds(usize),
+    /// The saved state was written by an unknown version
+    StateVersion(u8),
+    /// The saved state does not have the expected number of bytes
+    StateLength(usize),
+    /// Memory would be accessed past its end, starting from I
+    IndexOutOfBounds(u16),
+    /// The stack pointer points past the end of the stack
+    StackPointerOutOfBounds(usize),
+}
+
+impl std::fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Chip8Error::UnknownOpcode(opcode) => write!(f, "Unknown opcode {:04x}", opcode),
+            Chip8Error::PcOutOfBounds(pc) => write!(f, "Program counter {:#x} out of memory", pc),
+            Chip8Error::StateVersion(version) => write!(f, "Unknown state version {}", version),
+            Chip8Error::StateLength(len) => write!(f, "Invalid state of {} bytes", len),
+            Chip8Error::IndexOutOfBounds(index) => write!(f, "Index {:#x} out of memory", index),
+            Chip8Error::StackPointerOutOfBounds(sp) => {
+                write!(f, "Stack pointer {} out of stack", sp)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {}
+
+/// Around 700 instructions per second at 60 frames per second
+const INSTRUCTIONS_PER_FRAME: usize = 12;
+
+/// Version of the save state format, to be bumped when the layout changes
+const STATE_VERSION: u8 = 2;
+
+/// Size of the monochrome display in pixels
+const DISPLAY_WIDTH: usize = 64;
+const DISPLAY_HEIGHT: usize = 32;
+
+/// Version, registers, PC, I, SP, stack, halted, timers, keys, display, and memory
+const STATE_LEN: usize =
+    1 + 16 + 2 + 2 + 1 + 16 * 2 + 1 + 2 + 16 + DISPLAY_WIDTH * DISPLAY_HEIGHT + 4096;
+
+/// Where programs are loaded, as the memory below was reserved for the interpreter
+const PROGRAM_START: usize = 0x200;
+
+/// Where the built-in font is loaded
+const FONT_START: usize = 0x050;
+
+/// Bytes of each font sprite, one per row
+const FONT_SPRITE_SIZE: usize = 5;
+
+/// Sprites of the hexadecimal digits 0-F, 4 pixels wide and 5 rows tall
+const FONT: [u8; 16 * FONT_SPRITE_SIZE] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+/// Operations decoded from an opcode, with their operands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OpCode {
+    /// 0000: stop the execution
+    Halt,
+    /// 00EE: return from a function
+    Ret,
+    /// 1NNN: jump to address NNN
+    Jump(u16),
+    /// 2NNN: call the function at address NNN
+    Call(u16),
+    /// 6XKK: load KK into register X
+    Load(u8, u8),
+    /// 8XY4: add register Y to register X
+    AddXY(u8, u8),
+    /// 8XY5: subtract register Y from register X
+    SubXY(u8, u8),
+    /// 8XY7: subtract register X from register Y, storing the result in X
+    SubnXY(u8, u8),
+    /// 8XY6: shift right by one bit, see `Quirks::shift_vy`
+    ShiftRight(u8, u8),
+    /// 8XYE: shift left by one bit, see `Quirks::shift_vy`
+    ShiftLeft(u8, u8),
+    /// DXYN: draw the N bytes sprite at I at the position in registers X and Y
+    Draw(u8, u8, u8),
+    /// FX29: point I at the font sprite of the digit in register X
+    LoadSprite(u8),
+    /// FX55: store registers 0 to X in memory starting at I
+    StoreRegisters(u8),
+    /// FX65: load registers 0 to X from memory starting at I
+    LoadRegisters(u8),
+    /// Does not map to any implemented operation
+    Unknown(u16),
+}
+
+/// Decodes a raw opcode into the operation it maps to
+fn decode(raw: u16) -> OpCode {
+    // Decode instruction (4 nibbles: half of a byte)
+    let c = ((raw & 0xF000) >> 12) as u8;
+    let x = ((raw & 0x0F00) >> 8) as u8;
+    let y = ((raw & 0x00F0) >> 4) as u8;
+    let d = ((raw & 0x000F) >> 0) as u8;
+
+    let nnn = raw & 0x0FFF;
+    let kk = (raw & 0x00FF) as u8;
+
+    match (c, x, y, d) {
+        (0, 0, 0, 0) => OpCode::Halt,
+        (0, 0, 0xE, 0xE) => OpCode::Ret,
+        (0x1, _, _, _) => OpCode::Jump(nnn),
+        (0x2, _, _, _) => OpCode::Call(nnn),
+        (0x6, _, _, _) => OpCode::Load(x, kk),
+        (0x8, _, _, 0x4) => OpCode::AddXY(x, y),
+        (0x8, _, _, 0x5) => OpCode::SubXY(x, y),
+        (0x8, _, _, 0x6) => OpCode::ShiftRight(x, y),
+        (0x8, _, _, 0x7) => OpCode::SubnXY(x, y),
+        (0x8, _, _, 0xE) => OpCode::ShiftLeft(x, y),
+        (0xD, _, _, _) => OpCode::Draw(x, y, d),
+        (0xF, _, 0x2, 0x9) => OpCode::LoadSprite(x),
+        (0xF, _, 0x5, 0x5) => OpCode::StoreRegisters(x),
+        (0xF, _, 0x6, 0x5) => OpCode::LoadRegisters(x),
+        // Yet unimplemented
+        _ => OpCode::Unknown(raw),
+    }
+}
+
+/// Behaviors which differ between CHIP-8 implementations, as programs written
+/// for one of them may depend on it. The default follows most modern interpreters.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Quirks {
+    /// Shifts load register Y shifted into register X, as the original COSMAC VIP did,
+    /// instead of shifting register X in place
+    shift_vy: bool,
+    /// Storing and loading registers leaves I pointing past the last register,
+    /// as the original COSMAC VIP did, instead of leaving it unchanged
+    increment_i: bool,
+}
+
 struct Cpu {
     // R15 is used as a carry flag
     registers: [u8; 16],
@@ -13,11 +171,30 @@ struct Cpu {
     // Memory address of the next instruction
     program_counter: usize,
 
+    // Register I, which holds memory addresses
+    index: u16,
+
     memory: [u8; 4096],
 
     // Memory for storing addresses
     stack: [u16; 16],
     stack_pointer: usize,
+
+    // Set by the halt instruction
+    halted: bool,
+
+    // Count down to zero at 60Hz
+    delay_timer: u8,
+    sound_timer: u8,
+
+    // Keypad with keys 0-F, true while pressed
+    keys: [bool; 16],
+
+    // Pixels row by row, true when set
+    display: [bool; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+
+    // Not part of the state, as it configures the interpreter
+    quirks: Quirks,
 }
 
 impl std::fmt::Display for Cpu {
@@ -31,48 +208,169 @@ impl std::fmt::Display for Cpu {
 }
 
 impl Cpu {
-    fn run(&mut self) {
-        loop {
-            // Read opcode
-            let opcode = self.read_opcode();
+    fn new() -> Self {
+        Self {
+            registers: [0; 16],
+            program_counter: 0,
+            index: 0,
+            memory: [0; 4096],
+            stack: [0; 16],
+            stack_pointer: 0,
+            halted: false,
+            delay_timer: 0,
+            sound_timer: 0,
+            keys: [false; 16],
+            display: [false; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+            quirks: Quirks::default(),
+        }
+    }
+
+    /// Clears the whole state, ready to load a new program at the start address,
+    /// with the built-in font sprites loaded in the reserved memory.
+    /// Quirks are kept, as they do not belong to the state.
+    fn reset(&mut self) {
+        let quirks = self.quirks;
+        *self = Self::new();
+        self.quirks = quirks;
+        self.program_counter = PROGRAM_START;
+        self.memory[FONT_START..FONT_START + FONT.len()].copy_from_slice(&FONT);
+    }
+
+    /// Returns a snapshot of the whole state of the CPU
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = Vec::with_capacity(STATE_LEN);
+        state.push(STATE_VERSION);
+        state.extend_from_slice(&self.registers);
+        state.extend_from_slice(&(self.program_counter as u16).to_be_bytes());
+        state.extend_from_slice(&self.index.to_be_bytes());
+        state.push(self.stack_pointer as u8);
+        for addr in &self.stack {
+            state.extend_from_slice(&addr.to_be_bytes());
+        }
+        state.push(self.halted as u8);
+        state.push(self.delay_timer);
+        state.push(self.sound_timer);
+        state.extend(self.keys.iter().map(|&pressed| pressed as u8));
+        state.extend(self.display.iter().map(|&set| set as u8));
+        state.extend_from_slice(&self.memory);
+        state
+    }
 
-            // Read every two bytes (16 bit architecture)
-            self.program_counter += 2;
+    /// Restores a snapshot taken by `save_state`, leaving the CPU untouched on error
+    fn load_state(&mut self, state: &[u8]) -> Result<(), Chip8Error> {
+        if state.len() != STATE_LEN {
+            return Err(Chip8Error::StateLength(state.len()));
+        }
+        if state[0] != STATE_VERSION {
+            return Err(Chip8Error::StateVersion(state[0]));
+        }
 
-            // Decode instruction (4 nibbles: half of a byte)
-            let c = ((opcode & 0xF000) >> 12) as u8;
-            let x = ((opcode & 0x0F00) >> 8) as u8;
-            let y = ((opcode & 0x00F0) >> 4) as u8;
-            let d = ((opcode & 0x000F) >> 0) as u8;
+        let (registers, rest) = state[1..].split_at(16);
+        let read_u16 = |bytes: &[u8]| u16::from_be_bytes([bytes[0], bytes[1]]);
+        let program_counter = read_u16(rest) as usize;
+        let stack_pointer = rest[4] as usize;
 
-            let nnn = opcode & 0x0FFF;
+        // Values which would make the next instruction panic are rejected before changing anything
+        if program_counter >= self.memory.len() {
+            return Err(Chip8Error::PcOutOfBounds(program_counter));
+        }
+        if stack_pointer > self.stack.len() {
+            return Err(Chip8Error::StackPointerOutOfBounds(stack_pointer));
+        }
 
-            // Match decoded instruction to known opcodes
-            // Dispatch execution of operation to a function
-            match (c, x, y, d) {
-                // Halt
-                (0, 0, 0, 0) => return,
+        self.registers.copy_from_slice(registers);
+        self.program_counter = program_counter;
+        self.index = read_u16(&rest[2..]);
+        self.stack_pointer = stack_pointer;
 
-                // Return
-                (0, 0, 0xE, 0xE) => self.ret(),
+        let (stack, rest) = rest[5..].split_at(16 * 2);
+        for (addr, bytes) in self.stack.iter_mut().zip(stack.chunks(2)) {
+            *addr = read_u16(bytes);
+        }
+        self.halted = rest[0] != 0;
+        self.delay_timer = rest[1];
+        self.sound_timer = rest[2];
 
-                // Add
-                (0x8, _, _, 0x4) => self.add_xy(x, y),
+        let (keys, rest) = rest[3..].split_at(16);
+        for (pressed, &byte) in self.keys.iter_mut().zip(keys) {
+            *pressed = byte != 0;
+        }
 
-                // Jump
-                (0x2, _, _, _) => self.call(nnn),
+        let (display, memory) = rest.split_at(self.display.len());
+        for (set, &byte) in self.display.iter_mut().zip(display) {
+            *set = byte != 0;
+        }
+        self.memory.copy_from_slice(memory);
 
-                // Yet unimplemented
-                _ => todo!("opcode {:04x}", opcode),
+        Ok(())
+    }
+
+    /// Executes instructions until the program halts, as fast as possible
+    fn run(&mut self) -> Result<(), Chip8Error> {
+        while !self.halted {
+            self.run_frame(INSTRUCTIONS_PER_FRAME)?;
+        }
+        Ok(())
+    }
+
+    /// Executes up to a number of instructions, or until the program halts,
+    /// then ticks the timers once. It is meant to be called at each 60Hz
+    /// display refresh: a typical speed of 500-1000 instructions per second
+    /// means around 8-16 instructions per frame.
+    fn run_frame(&mut self, instructions: usize) -> Result<(), Chip8Error> {
+        for _ in 0..instructions {
+            if self.halted {
+                break;
             }
+            self.step()?;
+        }
+
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+
+        Ok(())
+    }
+
+    /// Executes the instruction pointed by the program counter
+    fn step(&mut self) -> Result<(), Chip8Error> {
+        // Read opcode
+        let opcode = self.read_opcode()?;
+
+        // Read every two bytes (16 bit architecture)
+        self.program_counter += 2;
+
+        // Dispatch execution of the decoded operation to a function
+        match decode(opcode) {
+            OpCode::Halt => self.halted = true,
+            OpCode::Ret => self.ret(),
+            OpCode::Jump(addr) => self.program_counter = addr as usize,
+            OpCode::Call(addr) => self.call(addr),
+            OpCode::Load(x, kk) => self.registers[x as usize] = kk,
+            OpCode::AddXY(x, y) => self.add_xy(x, y),
+            OpCode::SubXY(x, y) => self.sub_xy(x, y),
+            OpCode::SubnXY(x, y) => self.subn_xy(x, y),
+            OpCode::ShiftRight(x, y) => self.shift_right(x, y),
+            OpCode::ShiftLeft(x, y) => self.shift_left(x, y),
+            OpCode::Draw(x, y, n) => self.draw(x, y, n)?,
+            OpCode::LoadSprite(x) => self.load_sprite(x),
+            OpCode::StoreRegisters(x) => self.store_registers(x)?,
+            OpCode::LoadRegisters(x) => self.load_registers(x)?,
+            OpCode::Unknown(opcode) => return Err(Chip8Error::UnknownOpcode(opcode)),
         }
+
+        Ok(())
     }
 
-    fn read_opcode(&self) -> u16 {
+    fn read_opcode(&self) -> Result<u16, Chip8Error> {
+        // An opcode takes two bytes of memory
+        if self.program_counter + 1 >= self.memory.len() {
+            return Err(Chip8Error::PcOutOfBounds(self.program_counter));
+        }
+
         // Big-endian approach
         let high_byte = self.memory[self.program_counter] as u16;
         let low_byte = self.memory[self.program_counter + 1] as u16;
-        (high_byte << 8) | low_byte
+        Ok((high_byte << 8) | low_byte)
     }
 
     /// Adds x and y, storing the result in x
@@ -91,6 +389,135 @@ impl Cpu {
         }
     }
 
+    /// Subtracts y from x, storing the result in x
+    fn sub_xy(&mut self, x: u8, y: u8) {
+        let a = self.registers[x as usize];
+        let b = self.registers[y as usize];
+
+        let (v, borrow) = a.overflowing_sub(b);
+
+        self.registers[x as usize] = v;
+
+        // Unlike the carry of an addition, the flag is set when there is no borrow
+        if borrow {
+            self.registers[0xF] = 0;
+        } else {
+            self.registers[0xF] = 1;
+        }
+    }
+
+    /// Subtracts x from y, storing the result in x
+    fn subn_xy(&mut self, x: u8, y: u8) {
+        let a = self.registers[x as usize];
+        let b = self.registers[y as usize];
+
+        let (v, borrow) = b.overflowing_sub(a);
+
+        self.registers[x as usize] = v;
+
+        if borrow {
+            self.registers[0xF] = 0;
+        } else {
+            self.registers[0xF] = 1;
+        }
+    }
+
+    /// Returns the register to be shifted into x, according to the quirks
+    fn shift_source(&self, x: u8, y: u8) -> u8 {
+        if self.quirks.shift_vy {
+            self.registers[y as usize]
+        } else {
+            self.registers[x as usize]
+        }
+    }
+
+    /// Shifts right by one bit, storing the bit shifted out in the flag
+    fn shift_right(&mut self, x: u8, y: u8) {
+        let value = self.shift_source(x, y);
+        self.registers[x as usize] = value >> 1;
+        self.registers[0xF] = value & 0x1;
+    }
+
+    /// Shifts left by one bit, storing the bit shifted out in the flag
+    fn shift_left(&mut self, x: u8, y: u8) {
+        let value = self.shift_source(x, y);
+        self.registers[x as usize] = value << 1;
+        self.registers[0xF] = value >> 7;
+    }
+
+    /// Returns the memory range of registers 0 to x starting at I
+    fn registers_range(&self, x: u8) -> Result<std::ops::Range<usize>, Chip8Error> {
+        let start = self.index as usize;
+        let end = start + x as usize + 1;
+        if end > self.memory.len() {
+            return Err(Chip8Error::IndexOutOfBounds(self.index));
+        }
+        Ok(start..end)
+    }
+
+    fn store_registers(&mut self, x: u8) -> Result<(), Chip8Error> {
+        let range = self.registers_range(x)?;
+        self.memory[range].copy_from_slice(&self.registers[..=x as usize]);
+        if self.quirks.increment_i {
+            self.index += x as u16 + 1;
+        }
+        Ok(())
+    }
+
+    fn load_registers(&mut self, x: u8) -> Result<(), Chip8Error> {
+        let range = self.registers_range(x)?;
+        self.registers[..=x as usize].copy_from_slice(&self.memory[range]);
+        if self.quirks.increment_i {
+            self.index += x as u16 + 1;
+        }
+        Ok(())
+    }
+
+    /// Returns whether the pixel at column x and row y of the display is set
+    fn pixel(&self, x: usize, y: usize) -> bool {
+        self.display[y * DISPLAY_WIDTH + x]
+    }
+
+    /// Draws a sprite of n rows read from memory at I, at the position in x and y.
+    ///
+    /// Each byte is a row of 8 pixels, from the most significant bit, and each set bit
+    /// flips the pixel below it: drawing is a XOR, so drawing a sprite twice at the
+    /// same position erases it. Pixels past an edge wrap around to the opposite one.
+    /// The flag tells whether any pixel was turned off, which games use to detect
+    /// collisions between sprites.
+    fn draw(&mut self, x: u8, y: u8, n: u8) -> Result<(), Chip8Error> {
+        let start = self.index as usize;
+        let end = start + n as usize;
+        if end > self.memory.len() {
+            return Err(Chip8Error::IndexOutOfBounds(self.index));
+        }
+
+        let left = self.registers[x as usize] as usize;
+        let top = self.registers[y as usize] as usize;
+        let mut collision = false;
+
+        for (row, &byte) in self.memory[start..end].iter().enumerate() {
+            let py = (top + row) % DISPLAY_HEIGHT;
+            for col in 0..8 {
+                if byte & (0x80 >> col) == 0 {
+                    continue;
+                }
+                let px = (left + col) % DISPLAY_WIDTH;
+                let pixel = &mut self.display[py * DISPLAY_WIDTH + px];
+                collision |= *pixel;
+                *pixel = !*pixel;
+            }
+        }
+
+        self.registers[0xF] = collision as u8;
+        Ok(())
+    }
+
+    fn load_sprite(&mut self, x: u8) {
+        let digit = (self.registers[x as usize] & 0x0F) as usize;
+        self.index = (FONT_START + digit * FONT_SPRITE_SIZE) as u16;
+    }
+
     /// Calling a function is a three step process
     fn call(&mut self, addr: u16) {
         if self.stack_pointer > self.stack.len() {
@@ -124,25 +551,377 @@ impl Cpu {
 
 fn main() {
     // Init CPU
-    let mut cpu = Cpu {
-        registers: [0; 16],
-        program_counter: 0,
-        memory: [0; 4096],
-        stack: [0; 16],
-        stack_pointer: 0,
-    };
-
-    // Load operation in memory pointing by PC register
-    cpu.memory[0] = 0x80;
-    cpu.memory[1] = 0x14;
-    cpu.memory[0] = 0x80;
-    cpu.memory[1] = 0x24;
-
-    // Load operands into registers
-    cpu.registers[0] = 5;
-    cpu.registers[1] = 10;
+    let mut cpu = Cpu::new();
+    cpu.reset();
+
+    // Load operations in memory pointing by PC register
+    let program = asm::assemble(
+        "
+        LD V0, 5    ; Load operands into registers
+        LD V1, 10
+        ADD V0, V1
+        HALT
+        ",
+    )
+    .expect("Failed to assemble program");
+    cpu.memory[PROGRAM_START..PROGRAM_START + program.len()].copy_from_slice(&program);
+
+    // Snapshot the loaded program, so that it can be run again from the start
+    let state = cpu.save_state();
 
     // Perform operation
-    cpu.run();
+    if let Err(err) = cpu.run() {
+        eprintln!("{}", err);
+    }
     println!("{}", cpu);
+
+    // Rewind and run it once more
+    cpu.load_state(&state).expect("Failed to load state");
+    if let Err(err) = cpu.run() {
+        eprintln!("{}", err);
+    }
+    println!("Rewound\n{}", cpu);
+
+    // Draw the font sprite of the result digit at the top left corner,
+    // where VE, still zero, provides both coordinates
+    cpu.load_sprite(0);
+    cpu.draw(0xE, 0xE, FONT_SPRITE_SIZE as u8)
+        .expect("Failed to draw sprite");
+    for y in 0..FONT_SPRITE_SIZE {
+        let row: String = (0..8)
+            .map(|x| if cpu.pixel(x, y) { '#' } else { ' ' })
+            .collect();
+        println!("{}", row);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decode_opcodes() {
+        assert_eq!(decode(0x8014), OpCode::AddXY(0, 1));
+        assert_eq!(decode(0x2ABC), OpCode::Call(0xABC));
+        assert_eq!(decode(0x0000), OpCode::Halt);
+        assert_eq!(decode(0x00EE), OpCode::Ret);
+        assert_eq!(decode(0x1200), OpCode::Jump(0x200));
+        assert_eq!(decode(0x63FF), OpCode::Load(3, 0xFF));
+        assert_eq!(decode(0xFA29), OpCode::LoadSprite(0xA));
+        assert_eq!(decode(0x8015), OpCode::SubXY(0, 1));
+        assert_eq!(decode(0x8017), OpCode::SubnXY(0, 1));
+        assert_eq!(decode(0x8016), OpCode::ShiftRight(0, 1));
+        assert_eq!(decode(0x801E), OpCode::ShiftLeft(0, 1));
+        assert_eq!(decode(0xD125), OpCode::Draw(1, 2, 5));
+        assert_eq!(decode(0xF355), OpCode::StoreRegisters(3));
+        assert_eq!(decode(0xF365), OpCode::LoadRegisters(3));
+        assert_eq!(decode(0x8018), OpCode::Unknown(0x8018));
+    }
+
+    /// Runs a single opcode with V0 and V1 set, returning V0 and VF
+    fn run_with_operands(opcode: u16, v0: u8, v1: u8) -> (u8, u8) {
+        let mut cpu = Cpu::new();
+        cpu.registers[0] = v0;
+        cpu.registers[1] = v1;
+        cpu.memory[..2].copy_from_slice(&opcode.to_be_bytes());
+
+        cpu.step().unwrap();
+        (cpu.registers[0], cpu.registers[0xF])
+    }
+
+    #[test]
+    fn sub_xy() {
+        // No borrow sets the flag
+        assert_eq!(run_with_operands(0x8015, 10, 3), (7, 1));
+        assert_eq!(run_with_operands(0x8015, 3, 3), (0, 1));
+        // A borrow wraps around and clears the flag
+        assert_eq!(run_with_operands(0x8015, 3, 10), (249, 0));
+    }
+
+    #[test]
+    fn subn_xy() {
+        assert_eq!(run_with_operands(0x8017, 3, 10), (7, 1));
+        assert_eq!(run_with_operands(0x8017, 10, 3), (249, 0));
+    }
+
+    #[test]
+    fn shift_quirks() {
+        let run_shift = |opcode: u16, quirks: Quirks| {
+            let mut cpu = Cpu::new();
+            cpu.quirks = quirks;
+            cpu.registers[0] = 0b1000_0110;
+            cpu.registers[1] = 0b0000_0011;
+            cpu.memory[..2].copy_from_slice(&opcode.to_be_bytes());
+
+            cpu.step().unwrap();
+            (cpu.registers[0], cpu.registers[0xF])
+        };
+
+        let modern = Quirks::default();
+        assert_eq!(run_shift(0x8016, modern), (0b0100_0011, 0));
+        assert_eq!(run_shift(0x801E, modern), (0b0000_1100, 1));
+
+        let original = Quirks {
+            shift_vy: true,
+            ..Quirks::default()
+        };
+        assert_eq!(run_shift(0x8016, original), (0b0000_0001, 1));
+        assert_eq!(run_shift(0x801E, original), (0b0000_0110, 0));
+    }
+
+    #[test]
+    fn store_and_load_registers() {
+        for &increment_i in &[false, true] {
+            let mut cpu = Cpu::new();
+            cpu.reset();
+            cpu.quirks.increment_i = increment_i;
+            cpu.index = 0x300;
+            cpu.registers[..3].copy_from_slice(&[1, 2, 3]);
+            cpu.memory[PROGRAM_START..PROGRAM_START + 4].copy_from_slice(&[0xF2, 0x55, 0xF2, 0x65]);
+
+            cpu.step().unwrap();
+            assert_eq!(&cpu.memory[0x300..0x304], &[1, 2, 3, 0]);
+            let expected_index = if increment_i { 0x303 } else { 0x300 };
+            assert_eq!(cpu.index, expected_index);
+
+            // Loading reads from where I points now
+            cpu.registers[..3].copy_from_slice(&[0; 3]);
+            cpu.step().unwrap();
+            let expected = if increment_i { [0, 0, 0] } else { [1, 2, 3] };
+            assert_eq!(&cpu.registers[..3], &expected);
+        }
+
+        // The reset keeps the quirks
+        let mut cpu = Cpu::new();
+        cpu.quirks.shift_vy = true;
+        cpu.reset();
+        assert!(cpu.quirks.shift_vy);
+
+        cpu.index = 4094;
+        cpu.memory[PROGRAM_START..PROGRAM_START + 2].copy_from_slice(&[0xF2, 0x55]);
+        assert_eq!(cpu.step(), Err(Chip8Error::IndexOutOfBounds(4094)));
+    }
+
+    /// Returns a CPU ready to draw the font sprite of 0 at the position in V0 and V1
+    fn cpu_drawing_zero(x: u8, y: u8) -> Cpu {
+        let mut cpu = Cpu::new();
+        cpu.reset();
+        cpu.registers[0] = x;
+        cpu.registers[1] = y;
+        cpu.index = FONT_START as u16;
+        // Draw twice
+        cpu.memory[PROGRAM_START..PROGRAM_START + 4].copy_from_slice(&[0xD0, 0x15, 0xD0, 0x15]);
+        cpu
+    }
+
+    #[test]
+    fn draw_sprite() {
+        let mut cpu = cpu_drawing_zero(10, 20);
+
+        // Drawing onto empty space does not collide
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers[0xF], 0);
+        for (row, &byte) in FONT[..FONT_SPRITE_SIZE].iter().enumerate() {
+            for col in 0..8 {
+                let set = byte & (0x80 >> col) != 0;
+                assert_eq!(cpu.pixel(10 + col, 20 + row), set);
+            }
+        }
+        assert_eq!(cpu.display.iter().filter(|&&set| set).count(), 14);
+
+        // Drawing over itself collides and erases the sprite
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers[0xF], 1);
+        assert!(cpu.display.iter().all(|&set| !set));
+    }
+
+    #[test]
+    fn draw_wraps_around() {
+        let mut cpu = cpu_drawing_zero(62, 30);
+
+        cpu.step().unwrap();
+        // The top row 0xF0 spans the last two and the first two columns
+        assert!(cpu.pixel(62, 30) && cpu.pixel(63, 30));
+        assert!(cpu.pixel(0, 30) && cpu.pixel(1, 30));
+        // The last rows continue from the top
+        assert!(cpu.pixel(62, 0) && !cpu.pixel(63, 0) && cpu.pixel(1, 0));
+        assert!(cpu.pixel(62, 2) && cpu.pixel(1, 2));
+        assert!(!cpu.pixel(62, 3));
+        assert_eq!(cpu.registers[0xF], 0);
+    }
+
+    #[test]
+    fn unknown_opcode() {
+        let mut cpu = Cpu::new();
+        cpu.memory[0] = 0xF0;
+        cpu.memory[1] = 0xFF;
+
+        assert_eq!(cpu.run(), Err(Chip8Error::UnknownOpcode(0xF0FF)));
+        assert!(!cpu.halted);
+    }
+
+    #[test]
+    fn run_frame() {
+        let mut cpu = Cpu::new();
+        cpu.registers[1] = 1;
+        cpu.delay_timer = 2;
+
+        // Plenty of additions of register 1 to register 0, followed by a halt
+        for addr in (0..40).step_by(2) {
+            cpu.memory[addr] = 0x80;
+            cpu.memory[addr + 1] = 0x14;
+        }
+
+        cpu.run_frame(8).unwrap();
+        assert_eq!(cpu.registers[0], 8);
+        assert_eq!(cpu.delay_timer, 1);
+
+        // The halt cuts the frame short, while timers stop at zero
+        cpu.run_frame(100).unwrap();
+        assert_eq!(cpu.registers[0], 20);
+        assert!(cpu.halted);
+        cpu.run_frame(100).unwrap();
+        assert_eq!(cpu.delay_timer, 0);
+    }
+
+    #[test]
+    fn reset() {
+        let mut cpu = Cpu::new();
+        cpu.registers[3] = 7;
+        cpu.memory[0x300] = 0xAB;
+        cpu.stack_pointer = 2;
+        cpu.halted = true;
+
+        cpu.reset();
+        assert_eq!(cpu.registers, [0; 16]);
+        assert_eq!(cpu.memory[0x300], 0);
+        assert_eq!(cpu.stack_pointer, 0);
+        assert!(!cpu.halted);
+        assert_eq!(cpu.program_counter, PROGRAM_START);
+        assert_eq!(&cpu.memory[FONT_START..FONT_START + FONT.len()], &FONT[..]);
+    }
+
+    #[test]
+    fn load_font_sprite() {
+        let mut cpu = Cpu::new();
+        cpu.reset();
+        cpu.registers[0] = 0xA;
+        cpu.memory[PROGRAM_START] = 0xF0;
+        cpu.memory[PROGRAM_START + 1] = 0x29;
+
+        cpu.step().unwrap();
+        let sprite = cpu.index as usize;
+        assert_eq!(
+            &cpu.memory[sprite..sprite + FONT_SPRITE_SIZE],
+            &[0xF0, 0x90, 0xF0, 0x90, 0x90]
+        );
+    }
+
+    #[test]
+    fn save_and_load_state() {
+        let mut cpu = Cpu::new();
+        cpu.reset();
+        let program = asm::assemble(
+            "
+            LD V0, 1
+            LD V1, 2
+            CALL 0x20A
+            HALT
+            HALT
+            ADD V0, V1  ; 0x20A
+            ADD V0, V1
+            RET
+            ",
+        )
+        .unwrap();
+        cpu.memory[PROGRAM_START..PROGRAM_START + program.len()].copy_from_slice(&program);
+        cpu.delay_timer = 30;
+        cpu.keys[0xB] = true;
+        cpu.display[DISPLAY_WIDTH + 3] = true;
+
+        cpu.run_frame(4).unwrap();
+        let state = cpu.save_state();
+        assert_eq!(state.len(), STATE_LEN);
+        let (registers, program_counter, stack_pointer) =
+            (cpu.registers, cpu.program_counter, cpu.stack_pointer);
+
+        cpu.run().unwrap();
+        assert_ne!(cpu.registers, registers);
+
+        cpu.load_state(&state).unwrap();
+        assert_eq!(cpu.registers, registers);
+        assert_eq!(cpu.program_counter, program_counter);
+        assert_eq!(cpu.stack_pointer, stack_pointer);
+        assert_eq!(cpu.delay_timer, 29);
+        assert!(cpu.keys[0xB]);
+        assert!(cpu.pixel(3, 1));
+        assert!(!cpu.halted);
+        assert_eq!(cpu.save_state(), state);
+
+        // Resuming from the snapshot ends up the same way
+        cpu.run().unwrap();
+        assert_eq!(cpu.registers[0], 5);
+    }
+
+    #[test]
+    fn invalid_state() {
+        let mut cpu = Cpu::new();
+        let mut state = cpu.save_state();
+
+        assert_eq!(
+            cpu.load_state(&state[1..]),
+            Err(Chip8Error::StateLength(STATE_LEN - 1))
+        );
+
+        state[0] = STATE_VERSION + 1;
+        assert_eq!(
+            cpu.load_state(&state),
+            Err(Chip8Error::StateVersion(STATE_VERSION + 1))
+        );
+    }
+
+    #[test]
+    fn tampered_state() {
+        let mut cpu = Cpu::new();
+        cpu.reset();
+        let state = cpu.save_state();
+
+        // Version, then 16 registers, then the program counter, I, and the stack pointer
+        let pc_offset = 1 + 16;
+        let sp_offset = pc_offset + 4;
+
+        let mut tampered = state.clone();
+        tampered[1] = 0xAB;
+        tampered[sp_offset] = 200;
+        assert_eq!(
+            cpu.load_state(&tampered),
+            Err(Chip8Error::StackPointerOutOfBounds(200))
+        );
+
+        let mut tampered = state.clone();
+        tampered[1] = 0xAB;
+        tampered[pc_offset..pc_offset + 2].copy_from_slice(&0xFFFFu16.to_be_bytes());
+        assert_eq!(
+            cpu.load_state(&tampered),
+            Err(Chip8Error::PcOutOfBounds(0xFFFF))
+        );
+
+        // Nothing was restored from the rejected states
+        assert_eq!(cpu.save_state(), state);
+    }
+
+    #[test]
+    fn pc_out_of_bounds() {
+        let mut cpu = Cpu::new();
+
+        // Add at the very last opcode of memory, then there is nowhere to go
+        cpu.program_counter = 4094;
+        cpu.memory[4094] = 0x80;
+        cpu.memory[4095] = 0x14;
+        assert_eq!(cpu.run(), Err(Chip8Error::PcOutOfBounds(4096)));
+
+        // Half an opcode is not enough either
+        cpu.program_counter = 4095;
+        assert_eq!(cpu.step(), Err(Chip8Error::PcOutOfBounds(4095)));
+    }
 }
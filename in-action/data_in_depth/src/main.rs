@@ -47,6 +47,13 @@ fn endianness() {
         println!("This is a little-endian architecture");
     }
 
+    // The same orders, built with shifts instead of reinterpreting memory
+    let n: u32 = 0x0102_0304;
+    let be = to_be_bytes_manual(n);
+    let le = to_le_bytes_manual(n);
+    println!("{:02x?} {:02x?}", be, le);
+    assert_eq!(from_be_bytes_manual(be), from_le_bytes_manual(le));
+
     // How about bit-endianness?
     let big_endian: u8 = 0b0000_0001;
     if big_endian == 1 {
@@ -56,6 +63,26 @@ fn endianness() {
     }
 }
 
+/// Returns the bytes of an integer from the most significant one
+fn to_be_bytes_manual(n: u32) -> [u8; 4] {
+    [(n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8, n as u8]
+}
+
+/// Returns the bytes of an integer from the least significant one
+fn to_le_bytes_manual(n: u32) -> [u8; 4] {
+    [n as u8, (n >> 8) as u8, (n >> 16) as u8, (n >> 24) as u8]
+}
+
+/// Builds an integer from bytes starting with the most significant one
+fn from_be_bytes_manual(bytes: [u8; 4]) -> u32 {
+    (bytes[0] as u32) << 24 | (bytes[1] as u32) << 16 | (bytes[2] as u32) << 8 | bytes[3] as u32
+}
+
+/// Builds an integer from bytes starting with the least significant one
+fn from_le_bytes_manual(bytes: [u8; 4]) -> u32 {
+    (bytes[3] as u32) << 24 | (bytes[2] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[0] as u32
+}
+
 /// A 32 bit floating point number is made of 3 fields ((± signbit) mantissa ^ (2 ^ exponent)):
 /// A signbit, which determines wheter the number is positive or negative
 /// The exponent, biased 8 bits integer. Special cases: 0x00 (subnormal number); 0xFF (infinity)
@@ -70,6 +97,31 @@ fn deconstruct_f32(n: f32) -> (u32, u32, u32) {
     (signbit, exponent, fraction)
 }
 
+/// Tells which kind of number it is, looking at the special values of the exponent
+fn classify_f32(n: f32) -> &'static str {
+    let (_, exponent, fraction) = deconstruct_f32(n);
+    match (exponent, fraction) {
+        (0x00, 0) => "zero",
+        (0x00, _) => "subnormal",
+        (0xFF, 0) => "infinity",
+        (0xFF, _) => "nan",
+        _ => "normal",
+    }
+}
+
+/// Shows the three fields of a number with their labels
+fn format_f32_fields(n: f32) -> String {
+    let (signbit, exponent, fraction) = deconstruct_f32(n);
+    format!(
+        "{} ({}): sign {:01b} | exponent {:08b} | fraction {:023b}",
+        n,
+        classify_f32(n),
+        signbit,
+        exponent,
+        fraction
+    )
+}
+
 fn decode_f32_parts(signbit: u32, exponent: u32, fraction: u32) -> (f32, f32, f32) {
     let signed_one = (-1.0f32).powf(signbit as f32);
 
@@ -88,6 +140,55 @@ fn decode_f32_parts(signbit: u32, exponent: u32, fraction: u32) -> (f32, f32, f3
     (signed_one, exponent_part, mantissa_part)
 }
 
+/// Fields of a 32 bit floating point number
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct F32Parts {
+    /// Whether the number is negative
+    pub sign: bool,
+    /// Exponent with the bias removed, so -127 for zero and subnormal numbers,
+    /// and 128 for infinity and NaN
+    pub exponent: i32,
+    /// The 23 bits of the fraction, without the implicit leading one
+    pub mantissa: u32,
+}
+
+impl F32Parts {
+    const BIAS: i32 = 127;
+    const MANTISSA_BITS: i32 = 23;
+
+    pub fn from_f32(n: f32) -> Self {
+        let (signbit, exponent, fraction) = deconstruct_f32(n);
+        Self {
+            sign: signbit == 1,
+            exponent: exponent as i32 - Self::BIAS,
+            mantissa: fraction,
+        }
+    }
+
+    /// Computes the value from its fields
+    pub fn to_f32(&self) -> f32 {
+        let fraction = self.mantissa as f64 / (1u32 << Self::MANTISSA_BITS) as f64;
+
+        let magnitude = match self.exponent + Self::BIAS {
+            // Infinity, or NaN when the fraction is not zero
+            0xFF if self.mantissa == 0 => f64::INFINITY,
+            0xFF => f64::NAN,
+            // Zero and subnormal numbers have no implicit leading one,
+            // and the same exponent as the smallest normal numbers
+            0x00 => fraction * 2f64.powi(1 - Self::BIAS),
+            _ => (1.0 + fraction) * 2f64.powi(self.exponent),
+        };
+
+        // Every f32 is exactly representable as f64, so the cast does not round
+        let value = magnitude as f32;
+        if self.sign {
+            -value
+        } else {
+            value
+        }
+    }
+}
+
 fn floating_point() {
     let n: f32 = 42.42;
 
@@ -105,6 +206,13 @@ fn floating_point() {
         mantissa,
         sign * exponent * mantissa
     );
+
+    let parts = F32Parts::from_f32(n);
+    println!("{:?} = {}", parts, parts.to_f32());
+
+    for &n in [n, 0.0, f32::MIN_POSITIVE / 2.0, f32::INFINITY, f32::NAN].iter() {
+        println!("{}", format_f32_fields(n));
+    }
 }
 
 /// Q format is a fixed-point number, developed by Texas Instrument for embedded computing devices.
@@ -142,6 +250,19 @@ impl From<Q7> for f32 {
     }
 }
 
+/// Dot product of two Q7 vectors, as used by DSP code for filters.
+/// Products are accumulated in a wider integer, so that intermediate results do not overflow,
+/// and only the final sum saturates to the Q7 range.
+fn dot_q7(a: &[Q7], b: &[Q7]) -> Q7 {
+    assert_eq!(a.len(), b.len(), "Vectors of different lengths");
+
+    // The product of two Q7 numbers has 14 fractional bits
+    let sum: i32 = a.iter().zip(b).map(|(x, y)| x.0 as i32 * y.0 as i32).sum();
+    let sum = sum >> 7;
+
+    Q7(sum.clamp(i8::MIN as i32, i8::MAX as i32) as i8)
+}
+
 fn q_format() {
     // Out of bounds, we get the same value
     assert_eq!(Q7::from(10.0), Q7::from(1.0));
@@ -156,6 +277,11 @@ fn q_format() {
     let qf64 = Q7::from(0.5);
     assert_eq!(qf64, Q7(64));
     println!("Q::from(f64) = {:?}", qf64);
+
+    let a: Vec<Q7> = [0.5, -0.25, 0.125].iter().map(|&n| Q7::from(n)).collect();
+    let b: Vec<Q7> = [0.5, 0.5, -0.5].iter().map(|&n| Q7::from(n)).collect();
+    let dot = dot_q7(&a, &b);
+    println!("{:?} . {:?} = {:?} ({})", a, b, dot, f64::from(dot));
 }
 
 fn main() {
@@ -165,3 +291,128 @@ fn main() {
     floating_point();
     q_format();
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn manual_byte_order() {
+        for &n in [0, 1, 0x0102_0304, 0xdead_beef, u32::MAX].iter() {
+            assert_eq!(to_be_bytes_manual(n), n.to_be_bytes());
+            assert_eq!(to_le_bytes_manual(n), n.to_le_bytes());
+            assert_eq!(from_be_bytes_manual(n.to_be_bytes()), n);
+            assert_eq!(from_le_bytes_manual(n.to_le_bytes()), n);
+        }
+
+        assert_eq!(to_be_bytes_manual(0x0102_0304), [1, 2, 3, 4]);
+        assert_eq!(to_le_bytes_manual(0x0102_0304), [4, 3, 2, 1]);
+    }
+
+    #[test]
+    fn f32_parts_round_trip() {
+        let subnormal = f32::from_bits(0x0000_0001);
+        assert!(subnormal.is_subnormal());
+
+        let values = [
+            42.42,
+            -1.5,
+            0.0,
+            -0.0,
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+            f32::MAX,
+            f32::MIN_POSITIVE,
+            subnormal,
+            -f32::from_bits(0x0040_0000),
+        ];
+
+        for &value in values.iter() {
+            let parts = F32Parts::from_f32(value);
+            // Compare bits, so that the sign of zero counts too
+            assert_eq!(parts.to_f32().to_bits(), value.to_bits(), "{:?}", parts);
+        }
+
+        assert!(F32Parts::from_f32(f32::NAN).to_f32().is_nan());
+    }
+
+    #[test]
+    fn f32_parts_fields() {
+        let parts = F32Parts::from_f32(-6.0);
+        assert_eq!(
+            parts,
+            F32Parts {
+                sign: true,
+                exponent: 2,
+                mantissa: 1 << 22,
+            }
+        );
+
+        assert_eq!(F32Parts::from_f32(0.0).exponent, -127);
+        assert_eq!(F32Parts::from_f32(f32::INFINITY).exponent, 128);
+    }
+
+    #[test]
+    fn classify() {
+        assert_eq!(classify_f32(0.0), "zero");
+        assert_eq!(classify_f32(-0.0), "zero");
+        assert_eq!(classify_f32(f32::MIN_POSITIVE / 2.0), "subnormal");
+        assert_eq!(classify_f32(42.42), "normal");
+        assert_eq!(classify_f32(f32::MIN_POSITIVE), "normal");
+        assert_eq!(classify_f32(f32::INFINITY), "infinity");
+        assert_eq!(classify_f32(f32::NEG_INFINITY), "infinity");
+        assert_eq!(classify_f32(f32::NAN), "nan");
+    }
+
+    #[test]
+    fn fields() {
+        assert_eq!(
+            format_f32_fields(-6.0),
+            "-6 (normal): sign 1 | exponent 10000001 | fraction 10000000000000000000000"
+        );
+        assert_eq!(
+            format_f32_fields(0.0),
+            "0 (zero): sign 0 | exponent 00000000 | fraction 00000000000000000000000"
+        );
+    }
+
+    /// Compares the Q7 dot product against the floating point one
+    fn assert_dot_q7(a: &[f64], b: &[f64]) {
+        let expected: f64 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+
+        let a: Vec<Q7> = a.iter().map(|&n| Q7::from(n)).collect();
+        let b: Vec<Q7> = b.iter().map(|&n| Q7::from(n)).collect();
+        let dot = f64::from(dot_q7(&a, &b));
+
+        // Every conversion to Q7 and the final shift lose less than one step
+        let tolerance = (2 * a.len() + 1) as f64 / 128.0;
+        assert!(
+            (dot - expected).abs() <= tolerance,
+            "{} != {}",
+            dot,
+            expected
+        );
+    }
+
+    #[test]
+    fn dot_product_q7() {
+        // Exactly representable values give an exact result
+        let a = [Q7::from(0.5), Q7::from(-0.25), Q7::from(0.125)];
+        let b = [Q7::from(0.5), Q7::from(0.5), Q7::from(-0.5)];
+        assert_eq!(dot_q7(&a, &b), Q7::from(0.0625));
+
+        assert_dot_q7(&[0.5, -0.25, 0.125], &[0.5, 0.5, -0.5]);
+        assert_dot_q7(&[0.3, 0.1, -0.7, 0.2], &[-0.4, 0.9, 0.15, 0.6]);
+        assert_dot_q7(&[0.01, 0.02, 0.03], &[0.99, -0.98, 0.97]);
+        assert_dot_q7(&[], &[]);
+    }
+
+    #[test]
+    fn dot_product_q7_saturates() {
+        let a = [Q7::from(0.9); 4];
+        assert_eq!(dot_q7(&a, &a), Q7(127));
+
+        let b = [Q7::from(-0.9); 4];
+        assert_eq!(dot_q7(&a, &b), Q7(-128));
+    }
+}
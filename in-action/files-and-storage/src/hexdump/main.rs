@@ -1,25 +1,131 @@
 use std::io::prelude::*;
 
-fn main() {
-    // Open a file passed as cli argument
-    let program = std::env::args().nth(0).unwrap();
-    let arg = std::env::args().nth(1);
-    let file_path = arg.expect(&format!("usage: {} <file>", program));
-    let mut file = std::fs::File::open(&file_path).expect("Failed to open file");
+const BYTES_PER_LINE: usize = 16;
+
+const RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
 
-    // Read content of file into a buffer of bytes
+/// Reads a whole file, or standard input when the path is `-`
+fn read_input(path: &str) -> Vec<u8> {
     let mut buffer = vec![];
-    file.read_to_end(&mut buffer).unwrap();
+    if path == "-" {
+        std::io::stdin()
+            .lock()
+            .read_to_end(&mut buffer)
+            .expect("Failed to read standard input");
+    } else {
+        let mut file = std::fs::File::open(path).expect("Failed to open file");
+        file.read_to_end(&mut buffer).unwrap();
+    }
+    buffer
+}
+
+/// Returns the offsets where the two buffers differ, in increasing order.
+/// Where one is longer, its tail counts as differing.
+fn diff_offsets(a: &[u8], b: &[u8]) -> Vec<usize> {
+    let common = a.len().min(b.len());
+    let mismatches = a.iter().zip(b).enumerate().filter(|(_, (x, y))| x != y);
+    mismatches
+        .map(|(offset, _)| offset)
+        .chain(common..a.len().max(b.len()))
+        .collect()
+}
+
+/// Writes the bytes of a line starting at `start`, coloring those at differing offsets,
+/// and padding missing bytes so that columns stay aligned
+fn write_diff_line(out: &mut String, buffer: &[u8], start: usize, diffs: &[usize]) {
+    for offset in start..start + BYTES_PER_LINE {
+        match buffer.get(offset) {
+            Some(byte) if diffs.binary_search(&offset).is_ok() => {
+                out.push_str(&format!("{}{:02x}{} ", RED, byte, RESET))
+            }
+            Some(byte) => out.push_str(&format!("{:02x} ", byte)),
+            None => out.push_str("   "),
+        }
+    }
+}
+
+/// Prints both buffers side by side, highlighting the bytes which differ.
+/// With `stop`, it stops after the first line with a difference.
+fn print_diff(a: &[u8], b: &[u8], stop: bool) {
+    let diffs = diff_offsets(a, b);
+    let len = a.len().max(b.len());
+
+    for start in (0..len).step_by(BYTES_PER_LINE) {
+        let mut line = format!("[0x{:08x}] ", start);
+        write_diff_line(&mut line, a, start, &diffs);
+        line.push_str("| ");
+        write_diff_line(&mut line, b, start, &diffs);
+        println!("{}", line);
+
+        let differs = diffs
+            .iter()
+            .any(|&offset| (start..start + BYTES_PER_LINE).contains(&offset));
+        if stop && differs {
+            break;
+        }
+    }
+
+    println!("{} bytes differ", diffs.len());
+}
+
+fn main() {
+    // Read from the file passed as cli argument, or from standard input
+    // when there is no argument or it is `-`.
+    // With `--diff <other>` it compares against another file,
+    // and `--stop` ends the comparison at the first difference.
+    let mut path = None;
+    let mut other = None;
+    let mut stop = false;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--diff" => other = Some(args.next().expect("Missing file to diff against")),
+            "--stop" => stop = true,
+            _ => path = Some(arg),
+        }
+    }
+
+    // Read content into a buffer of bytes
+    let buffer = read_input(path.as_deref().unwrap_or("-"));
+
+    if let Some(other) = other {
+        print_diff(&buffer, &read_input(&other), stop);
+        return;
+    }
 
-    let bytes_per_line = 16;
     let mut position = 0;
     // Print its bytes in hexadecimal format
-    for line in buffer.chunks(bytes_per_line) {
+    for line in buffer.chunks(BYTES_PER_LINE) {
         print!("[0x{:08x}] ", position);
         for byte in line {
             print!("{:02x} ", byte);
         }
         println!();
-        position += bytes_per_line;
+        position += BYTES_PER_LINE;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn diff() {
+        assert_eq!(diff_offsets(b"same", b"same"), Vec::<usize>::new());
+        assert_eq!(diff_offsets(b"abcdef", b"abXdeY"), vec![2, 5]);
+
+        // The tail of the longer buffer differs, whichever it is
+        assert_eq!(diff_offsets(b"abc", b"aXcde"), vec![1, 3, 4]);
+        assert_eq!(diff_offsets(b"abcde", b"ab"), vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn diff_line() {
+        let mut line = String::new();
+        write_diff_line(&mut line, b"ab", 0, &[1]);
+        assert!(line.starts_with("61 \x1b[31m62\x1b[0m "));
+        assert_eq!(line.len(), "61 \x1b[31m62\x1b[0m ".len() + 14 * 3);
     }
 }
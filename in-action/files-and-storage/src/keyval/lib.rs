@@ -1,8 +1,12 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use serde::{de::DeserializeOwned, Serialize};
+use std::borrow::Cow;
 use std::io::{self, BufReader, BufWriter, Write};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     io::{Read, Seek, SeekFrom},
+    ops::Bound,
 };
 
 #[macro_use]
@@ -18,23 +22,124 @@ pub struct Pair {
     pub value: ByteString,
 }
 
+/// JSON representation of a pair, where arbitrary bytes are encoded as base64
+#[derive(Serialize, Deserialize)]
+struct JsonPair {
+    key: String,
+    value: String,
+}
+
+/// Highest bit of the value-length, set when the value is stored compressed
+const COMPRESSED_FLAG: u32 = 1 << 31;
+
+/// Maps every key to the position of its latest record in the store file
+pub trait Index: Default {
+    fn get(&self, key: &ByteStr) -> Option<u64>;
+    fn insert(&mut self, key: ByteString, position: u64);
+    /// Returns the positions of all the indexed records
+    fn positions(&self) -> Vec<u64>;
+    /// Returns every key with the position of its record
+    fn entries(&self) -> Vec<(&ByteStr, u64)>;
+}
+
+/// Unordered index with fast point lookups
+pub type HashIndex = HashMap<ByteString, u64>;
+
+/// Ordered index, which makes point lookups a bit slower (O(log n) instead of O(1))
+/// but keeps keys sorted so they can be scanned in order
+pub type OrderedIndex = BTreeMap<ByteString, u64>;
+
+impl Index for HashIndex {
+    fn get(&self, key: &ByteStr) -> Option<u64> {
+        HashMap::get(self, key).copied()
+    }
+
+    fn insert(&mut self, key: ByteString, position: u64) {
+        HashMap::insert(self, key, position);
+    }
+
+    fn positions(&self) -> Vec<u64> {
+        self.values().copied().collect()
+    }
+
+    fn entries(&self) -> Vec<(&ByteStr, u64)> {
+        self.iter()
+            .map(|(key, &pos)| (key.as_slice(), pos))
+            .collect()
+    }
+}
+
+impl Index for OrderedIndex {
+    fn get(&self, key: &ByteStr) -> Option<u64> {
+        BTreeMap::get(self, key).copied()
+    }
+
+    fn insert(&mut self, key: ByteString, position: u64) {
+        BTreeMap::insert(self, key, position);
+    }
+
+    fn positions(&self) -> Vec<u64> {
+        self.values().copied().collect()
+    }
+
+    fn entries(&self) -> Vec<(&ByteStr, u64)> {
+        self.iter()
+            .map(|(key, &pos)| (key.as_slice(), pos))
+            .collect()
+    }
+}
+
 /// This structure uses Bitcask as file format.
 /// Every record has two sections:
 /// - A fixed-length header with 3 values:
 ///   - checksum (4 bytes)
 ///   - key-length (4 bytes)
-///   - value-length (4 bytes)
+///   - value-length (4 bytes), whose highest bit flags a compressed value
 /// - A variable length body with 2 values:
 ///   - key (key-length bytes)
 ///   - value (value-length bytes).
+///
+/// With `Store::set_compression`, values are deflated before being written.
+/// The flag is per record, so compressed and plain records can share the same file.
+///
+/// The index defaults to a `HashMap`, use `Store::open_ordered` for a `BTreeMap`
+/// index which supports sorted range scans.
+///
+/// `Store::close` saves the index to a hint file next to the store file, named like it
+/// with a `.hint` suffix, so the next `load` only scans records written afterwards.
+/// The hint file is an optimization: dropping a store without closing it is safe,
+/// as `load` then scans the records the hint does not cover.
 #[derive(Debug)]
-pub struct Store {
+pub struct Store<I: Index = HashIndex> {
     file: std::fs::File,
-    pub index: HashMap<ByteString, u64>,
+    path: std::path::PathBuf,
+    pub index: I,
+    compress_values: bool,
 }
 
 impl Store {
     pub fn open(file_path: &std::path::Path) -> io::Result<Store> {
+        Store::open_with_index(file_path)
+    }
+}
+
+impl Store<OrderedIndex> {
+    /// Opens a store whose index keeps keys sorted
+    pub fn open_ordered(file_path: &std::path::Path) -> io::Result<Store<OrderedIndex>> {
+        Store::open_with_index(file_path)
+    }
+
+    /// Returns the keys within `[lo, hi)` in sorted order
+    pub fn range(&self, lo: &ByteStr, hi: &ByteStr) -> Vec<&ByteStr> {
+        self.index
+            .range::<ByteStr, _>((Bound::Included(lo), Bound::Excluded(hi)))
+            .map(|(key, _)| key.as_slice())
+            .collect()
+    }
+}
+
+impl<I: Index> Store<I> {
+    fn open_with_index(file_path: &std::path::Path) -> io::Result<Store<I>> {
         // The ? operator will return the error if open fails
         let file = std::fs::OpenOptions::new()
             .read(true)
@@ -44,21 +149,50 @@ impl Store {
             .open(file_path)?;
         Ok(Store {
             file,
-            index: HashMap::new(),
+            path: file_path.to_path_buf(),
+            index: I::default(),
+            compress_values: false,
         })
     }
 
-    fn process_record<R: Read>(file: &mut R) -> io::Result<Pair> {
-        // Read checksum and data
+    /// Sets whether values written from now on are compressed.
+    /// Values are stored plain anyway when compression does not make them smaller.
+    pub fn set_compression(&mut self, enabled: bool) {
+        self.compress_values = enabled;
+    }
+
+    /// Returns the path of the file backing this store
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+
+    /// Returns the size in bytes of the file backing this store
+    pub fn file_size(&self) -> io::Result<u64> {
+        Ok(self.file.metadata()?.len())
+    }
+
+    /// Reads the checksum, key-length, and value-length of a record,
+    /// along with whether its value is compressed
+    fn read_header<R: Read>(file: &mut R) -> io::Result<(u32, u32, u32, bool)> {
         let checksum = file.read_u32::<LittleEndian>()?;
         let key_len = file.read_u32::<LittleEndian>()?;
         let val_len = file.read_u32::<LittleEndian>()?;
+        let compressed = val_len & COMPRESSED_FLAG != 0;
+        Ok((checksum, key_len, val_len & !COMPRESSED_FLAG, compressed))
+    }
+
+    fn process_record<R: Read>(file: &mut R) -> io::Result<Pair> {
+        // Read checksum and data
+        let (checksum, key_len, val_len, compressed) = Self::read_header(file)?;
         let data_len = key_len + val_len;
 
         let mut data = ByteString::with_capacity(data_len as usize);
 
         file.take(data_len as u64).read_to_end(&mut data)?;
-        debug_assert_eq!(data.len(), data_len as usize);
+        if data.len() != data_len as usize {
+            // The record was cut short
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
 
         // Apply checking function to data and compare with the read one
         let checksum_ieee = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
@@ -70,54 +204,324 @@ impl Store {
             );
         }
 
-        let value = data.split_off(key_len as usize);
+        let mut value = data.split_off(key_len as usize);
         let key = data;
 
+        if compressed {
+            let mut decoder = flate2::read::DeflateDecoder::new(value.as_slice());
+            let mut decompressed = ByteString::new();
+            decoder
+                .read_to_end(&mut decompressed)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            value = decompressed;
+        }
+
         Ok(Pair { key, value })
     }
 
+    /// Builds the index from the store file.
+    /// A crash while writing can leave a partial record at the end of the file,
+    /// which is discarded by truncating the file to the last complete record.
     pub fn load(&mut self) -> io::Result<()> {
+        let start = self.load_hint()?;
+        let file_size = self.file_size()?;
+
         let mut f = std::io::BufReader::new(&mut self.file);
+        f.seek(SeekFrom::Start(start))?;
 
-        loop {
+        let end = loop {
             let current_position = f.seek(SeekFrom::Current(0))?;
 
-            let maybe_pair = Store::process_record(&mut f);
+            let maybe_pair = Self::process_record(&mut f);
             let pair = match maybe_pair {
                 Ok(pair) => pair,
                 Err(err) => match err.kind() {
                     io::ErrorKind::UnexpectedEof => {
-                        break;
+                        break current_position;
                     }
                     _ => return Err(err),
                 },
             };
 
             self.index.insert(pair.key, current_position);
+        };
+
+        if end < file_size {
+            eprintln!(
+                "Discarding {} bytes of a partial record at the end of {}",
+                file_size - end,
+                self.path.display()
+            );
+            self.file.set_len(end)?;
         }
 
         Ok(())
     }
 
+    fn hint_path(&self) -> std::path::PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".hint");
+        path.into()
+    }
+
+    /// Fills the index from the hint file, if any, returning the length of the store file
+    /// it covers. Records after that length still need to be scanned.
+    /// A hint which does not fit the store file is ignored.
+    fn load_hint(&mut self) -> io::Result<u64> {
+        let hint = match std::fs::File::open(self.hint_path()) {
+            Ok(hint) => hint,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(err),
+        };
+
+        match Self::read_hint(&mut BufReader::new(hint)) {
+            Ok((covered_len, index)) if covered_len <= self.file_size()? => {
+                self.index = index;
+                Ok(covered_len)
+            }
+            // Truncated or stale
+            Ok(_) => Ok(0),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(0),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Reads the covered length of the store file followed by
+    /// key-length (4 bytes), position (8 bytes), and key of each entry
+    fn read_hint<R: Read>(hint: &mut R) -> io::Result<(u64, I)> {
+        let covered_len = hint.read_u64::<LittleEndian>()?;
+        let count = hint.read_u64::<LittleEndian>()?;
+
+        let mut index = I::default();
+        for _ in 0..count {
+            let key_len = hint.read_u32::<LittleEndian>()?;
+            let position = hint.read_u64::<LittleEndian>()?;
+            let mut key = ByteString::with_capacity(key_len as usize);
+            hint.take(key_len as u64).read_to_end(&mut key)?;
+            if key.len() != key_len as usize {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            index.insert(key, position);
+        }
+
+        Ok((covered_len, index))
+    }
+
+    /// Flushes the store file and writes the hint file, consuming the store
+    pub fn close(mut self) -> io::Result<()> {
+        self.file.flush()?;
+        self.file.sync_data()?;
+
+        let entries = self.index.entries();
+        let hint_path = self.hint_path();
+
+        // Write to a temporary file first, so a crash never leaves a partial hint
+        let mut tmp_path = hint_path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let mut writer = BufWriter::new(std::fs::File::create(&tmp_path)?);
+        writer.write_u64::<LittleEndian>(self.file_size()?)?;
+        writer.write_u64::<LittleEndian>(entries.len() as u64)?;
+        for (key, position) in entries {
+            writer.write_u32::<LittleEndian>(key.len() as u32)?;
+            writer.write_u64::<LittleEndian>(position)?;
+            writer.write_all(key)?;
+        }
+        writer.into_inner()?.sync_data()?;
+
+        std::fs::rename(&tmp_path, &hint_path)
+    }
+
+    /// Walks the file returning `(offset, checksum, key_len, val_len)` of every record.
+    /// Checksums are not verified and trailing garbage is ignored, which makes this
+    /// useful to inspect a store file suspected to be corrupt.
+    pub fn dump_records(&mut self) -> io::Result<Vec<(u64, u32, u32, u32)>> {
+        let mut f = BufReader::new(&mut self.file);
+        f.seek(SeekFrom::Start(0))?;
+
+        let mut records = vec![];
+
+        loop {
+            let offset = f.stream_position()?;
+
+            let (checksum, key_len, val_len, _) = match Self::read_header(&mut f) {
+                Ok(header) => header,
+                Err(err) => match err.kind() {
+                    io::ErrorKind::UnexpectedEof => break,
+                    _ => return Err(err),
+                },
+            };
+
+            records.push((offset, checksum, key_len, val_len));
+
+            // Skip the body without reading it
+            f.seek(SeekFrom::Current(key_len as i64 + val_len as i64))?;
+        }
+
+        Ok(records)
+    }
+
+    /// Recomputes the checksum of every record, returning the offsets of those which fail.
+    /// Record bodies are streamed through the checksum, so values are never held in memory.
+    /// A record truncated at the end of the file counts as failed.
+    pub fn verify(&mut self) -> io::Result<Vec<u64>> {
+        let mut f = BufReader::new(&mut self.file);
+        f.seek(SeekFrom::Start(0))?;
+
+        let checksum_ieee = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+        let mut failed = vec![];
+        let mut chunk = [0u8; 4096];
+
+        loop {
+            let offset = f.stream_position()?;
+
+            let (checksum, key_len, val_len, _) = match Self::read_header(&mut f) {
+                Ok(header) => header,
+                Err(err) => match err.kind() {
+                    io::ErrorKind::UnexpectedEof => break,
+                    _ => return Err(err),
+                },
+            };
+
+            let mut digest = checksum_ieee.digest();
+            let mut body = (&mut f).take(key_len as u64 + val_len as u64);
+            let mut body_len = 0;
+            loop {
+                let read = body.read(&mut chunk)?;
+                if read == 0 {
+                    break;
+                }
+                digest.update(&chunk[..read]);
+                body_len += read as u64;
+            }
+
+            if body_len != key_len as u64 + val_len as u64 {
+                failed.push(offset);
+                break;
+            }
+
+            if digest.finalize() != checksum {
+                failed.push(offset);
+            }
+        }
+
+        Ok(failed)
+    }
+
     pub fn get(&mut self, key: &ByteStr) -> io::Result<Option<ByteString>> {
         let position = match self.index.get(key) {
-            Some(p) => *p,
+            Some(p) => p,
             None => return Ok(None),
         };
 
         let mut reader = BufReader::new(&mut self.file);
         reader.seek(SeekFrom::Start(position))?;
-        let pair = Store::process_record(&mut reader)?;
+        let pair = Self::process_record(&mut reader)?;
 
         Ok(Some(pair.value))
     }
 
+    /// Returns all the pairs which have not been deleted, in file order
+    fn live_pairs(&mut self) -> io::Result<Vec<Pair>> {
+        let mut positions = self.index.positions();
+        positions.sort_unstable();
+
+        let mut reader = BufReader::new(&mut self.file);
+        let mut pairs = Vec::with_capacity(positions.len());
+        for position in positions {
+            reader.seek(SeekFrom::Start(position))?;
+            let pair = Self::process_record(&mut reader)?;
+            // Deleted keys are stored with an empty value
+            if !pair.value.is_empty() {
+                pairs.push(pair);
+            }
+        }
+
+        Ok(pairs)
+    }
+
+    /// Returns how many live records have each value length.
+    /// Only record headers are read, unless values are compressed.
+    pub fn value_size_histogram(&mut self) -> io::Result<BTreeMap<usize, usize>> {
+        let mut positions = self.index.positions();
+        positions.sort_unstable();
+
+        let mut reader = BufReader::new(&mut self.file);
+        let mut histogram = BTreeMap::new();
+        for position in positions {
+            reader.seek(SeekFrom::Start(position))?;
+            let (_, _, mut val_len, compressed) = Self::read_header(&mut reader)?;
+            if compressed {
+                // The length of the value before compression
+                reader.seek(SeekFrom::Start(position))?;
+                val_len = Self::process_record(&mut reader)?.value.len() as u32;
+            }
+
+            // Deleted keys are stored with an empty value
+            if val_len > 0 {
+                *histogram.entry(val_len as usize).or_insert(0) += 1;
+            }
+        }
+
+        Ok(histogram)
+    }
+
+    /// Writes all live records as a JSON array of `{ "key": base64, "value": base64 }` objects
+    pub fn export_json(&mut self, writer: impl Write) -> io::Result<()> {
+        let pairs: Vec<JsonPair> = self
+            .live_pairs()?
+            .into_iter()
+            .map(|pair| JsonPair {
+                key: BASE64.encode(pair.key),
+                value: BASE64.encode(pair.value),
+            })
+            .collect();
+
+        serde_json::to_writer(writer, &pairs)?;
+        Ok(())
+    }
+
+    /// Inserts all the records of a JSON array written by `export_json`
+    pub fn import_json(&mut self, reader: impl Read) -> io::Result<()> {
+        let pairs: Vec<JsonPair> = serde_json::from_reader(reader)?;
+
+        let decode = |data: &str| {
+            BASE64
+                .decode(data)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+        };
+
+        for pair in pairs {
+            self.insert(&decode(&pair.key)?, &decode(&pair.value)?)?;
+        }
+
+        Ok(())
+    }
+
     #[inline]
     pub fn delete(&mut self, key: &ByteStr) -> io::Result<()> {
         self.insert(key, b"")
     }
 
+    /// Returns the deflated value if it is smaller than the original one
+    fn compress(value: &ByteStr) -> io::Result<Option<ByteString>> {
+        let mut encoder =
+            flate2::write::DeflateEncoder::new(ByteString::new(), flate2::Compression::default());
+        encoder.write_all(value)?;
+        let compressed = encoder.finish()?;
+        Ok(Some(compressed).filter(|compressed| compressed.len() < value.len()))
+    }
+
     fn insert_but_ignore_index(&mut self, key: &ByteStr, value: &ByteStr) -> io::Result<u64> {
+        // Tombstones of deleted keys stay empty
+        let mut flag = 0;
+        let mut value = Cow::Borrowed(value);
+        if self.compress_values && !value.is_empty() {
+            if let Some(compressed) = Self::compress(&value)? {
+                value = Cow::Owned(compressed);
+                flag = COMPRESSED_FLAG;
+            }
+        }
+
         // Make space for a new record
         let key_len = key.len();
         let val_len = value.len();
@@ -128,7 +532,7 @@ impl Store {
             tmp.push(*byte);
         }
 
-        for byte in value {
+        for byte in value.iter() {
             tmp.push(*byte);
         }
 
@@ -140,7 +544,7 @@ impl Store {
         let checksum = checksum_ieee.checksum(&tmp);
         writer.write_u32::<LittleEndian>(checksum)?;
         writer.write_u32::<LittleEndian>(key_len as u32)?;
-        writer.write_u32::<LittleEndian>(val_len as u32)?;
+        writer.write_u32::<LittleEndian>(val_len as u32 | flag)?;
         writer.write_all(&mut tmp)?;
         writer.flush()?;
 
@@ -159,12 +563,69 @@ impl Store {
     pub fn update(&mut self, key: &ByteStr, value: &ByteStr) -> io::Result<()> {
         self.insert(key, value)
     }
+
+    /// Writes a new record with `extra` appended to the current value of a key,
+    /// a missing key is treated as an empty value
+    pub fn append(&mut self, key: &ByteStr, extra: &ByteStr) -> io::Result<()> {
+        let mut value = self.get(key)?.unwrap_or_default();
+        value.extend_from_slice(extra);
+        self.insert(key, &value)
+    }
+}
+
+/// Store of typed keys and values, which are serialized with bincode
+/// before being handed to the byte-level `Store`
+#[derive(Debug)]
+pub struct TypedStore<K, V, I: Index = HashIndex> {
+    pub store: Store<I>,
+    types: std::marker::PhantomData<(K, V)>,
+}
+
+impl<K: Serialize, V: Serialize + DeserializeOwned, I: Index> TypedStore<K, V, I> {
+    pub fn new(store: Store<I>) -> Self {
+        Self {
+            store,
+            types: std::marker::PhantomData,
+        }
+    }
+
+    fn serialize<T: Serialize>(data: &T) -> io::Result<ByteString> {
+        bincode::serialize(data).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    pub fn get(&mut self, key: &K) -> io::Result<Option<V>> {
+        let value = match self.store.get(&Self::serialize(key)?)? {
+            // Deleted keys are stored with an empty value
+            Some(value) if !value.is_empty() => value,
+            _ => return Ok(None),
+        };
+
+        let value = bincode::deserialize(&value)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        Ok(Some(value))
+    }
+
+    pub fn insert(&mut self, key: &K, value: &V) -> io::Result<()> {
+        self.store
+            .insert(&Self::serialize(key)?, &Self::serialize(value)?)
+    }
+
+    pub fn delete(&mut self, key: &K) -> io::Result<()> {
+        self.store.delete(&Self::serialize(key)?)
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    /// Returns a path in the temporary directory, removing any leftover from previous runs
+    fn temp_store_path(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("keyval-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
     fn write_numbers_to_file() -> (u32, i8, f64) {
         let mut w = vec![];
 
@@ -234,4 +695,250 @@ mod test {
         assert_eq!(abcd, &[97u8, 98, 99, 100]);
         assert_eq!(parity_bit(abcd), 0);
     }
+
+    #[test]
+    fn ordered_range() {
+        let path = temp_store_path("ordered_range");
+        let mut store = Store::open_ordered(&path).unwrap();
+
+        for key in [b"delta", b"alpha", b"echoo", b"bravo", b"charl"].iter() {
+            store.insert(*key, b"value").unwrap();
+        }
+
+        let keys = store.range(b"b", b"e");
+        assert_eq!(keys, vec![&b"bravo"[..], b"charl", b"delta"]);
+
+        // Point lookups still work with an ordered index
+        assert_eq!(store.get(b"alpha").unwrap(), Some(b"value".to_vec()));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn export_import_json() {
+        let path = temp_store_path("export_json");
+        let mut store = Store::open(&path).unwrap();
+        store.insert(b"key", b"value").unwrap();
+        store.insert(b"\x00\xff", b"\x01\x02\x03").unwrap();
+        store.insert(b"gone", b"soon").unwrap();
+        store.delete(b"gone").unwrap();
+
+        let mut json = vec![];
+        store.export_json(&mut json).unwrap();
+
+        let other_path = temp_store_path("import_json");
+        let mut other = Store::open(&other_path).unwrap();
+        other.import_json(json.as_slice()).unwrap();
+
+        assert_eq!(other.get(b"key").unwrap(), Some(b"value".to_vec()));
+        assert_eq!(
+            other.get(b"\x00\xff").unwrap(),
+            Some(b"\x01\x02\x03".to_vec())
+        );
+        assert_eq!(other.get(b"gone").unwrap(), None);
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&other_path).unwrap();
+    }
+
+    #[test]
+    fn path_and_file_size() {
+        let path = temp_store_path("file_size");
+        let mut store = Store::open(&path).unwrap();
+        assert_eq!(store.path(), path.as_path());
+        assert_eq!(store.file_size().unwrap(), 0);
+
+        // Header of 12 bytes followed by key and value
+        store.insert(b"key", b"value").unwrap();
+        assert_eq!(store.file_size().unwrap(), 12 + 3 + 5);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn verify() {
+        let path = temp_store_path("verify");
+        let mut store = Store::open(&path).unwrap();
+        store.insert(b"one", b"1").unwrap();
+        store.insert(b"two", b"22").unwrap();
+        store.insert(b"three", b"333").unwrap();
+        assert!(store.verify().unwrap().is_empty());
+
+        // Flip the last byte of the value of the second record
+        let bad_offset = 12 + 3 + 1;
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[bad_offset + 12 + 3 + 1] ^= 0xff;
+        std::fs::write(&path, bytes).unwrap();
+
+        let mut store = Store::open(&path).unwrap();
+        assert_eq!(store.verify().unwrap(), vec![bad_offset as u64]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn append() {
+        let path = temp_store_path("append");
+        let mut store = Store::open(&path).unwrap();
+
+        store.append(b"log", b"first;").unwrap();
+        store.append(b"log", b"second;").unwrap();
+        assert_eq!(store.get(b"log").unwrap(), Some(b"first;second;".to_vec()));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn close_and_reload() {
+        let path = temp_store_path("close");
+        let hint_path = temp_store_path("close.hint");
+        let mut store = Store::open(&path).unwrap();
+        store.insert(b"one", b"1").unwrap();
+        store.insert(b"two", b"2").unwrap();
+        store.update(b"one", b"uno").unwrap();
+        store.delete(b"two").unwrap();
+        store.close().unwrap();
+        assert!(hint_path.exists());
+
+        let mut store = Store::open(&path).unwrap();
+        store.load().unwrap();
+        assert_eq!(store.get(b"one").unwrap(), Some(b"uno".to_vec()));
+        assert_eq!(store.get(b"two").unwrap(), Some(vec![]));
+
+        // Records written after the hint are found by scanning, even without closing
+        store.insert(b"three", b"3").unwrap();
+        drop(store);
+
+        let mut store = Store::open(&path).unwrap();
+        store.load().unwrap();
+        assert_eq!(store.get(b"one").unwrap(), Some(b"uno".to_vec()));
+        assert_eq!(store.get(b"three").unwrap(), Some(b"3".to_vec()));
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&hint_path).unwrap();
+    }
+
+    #[test]
+    fn value_size_histogram() {
+        let path = temp_store_path("histogram");
+        let mut store = Store::open(&path).unwrap();
+        store.insert(b"a", b"1").unwrap();
+        store.insert(b"b", b"2").unwrap();
+        store.insert(b"c", b"333").unwrap();
+        store.insert(b"d", b"4444").unwrap();
+        // Only the latest value of a key counts
+        store.update(b"d", b"44").unwrap();
+        store.insert(b"e", b"gone").unwrap();
+        store.delete(b"e").unwrap();
+
+        let histogram = store.value_size_histogram().unwrap();
+        let expected: BTreeMap<usize, usize> = [(1, 2), (2, 1), (3, 1)].iter().copied().collect();
+        assert_eq!(histogram, expected);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn recover_partial_record() {
+        let path = temp_store_path("recover");
+        let mut store = Store::open(&path).unwrap();
+        store.insert(b"key", b"value").unwrap();
+        let valid_len = store.file_size().unwrap();
+
+        // A record cut short in the middle of its header
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes.extend_from_slice(&[0xde, 0xad, 0xbe, 0xef, 3, 0]);
+        std::fs::write(&path, &bytes).unwrap();
+
+        let mut store = Store::open(&path).unwrap();
+        store.load().unwrap();
+        assert_eq!(store.get(b"key").unwrap(), Some(b"value".to_vec()));
+        assert_eq!(store.file_size().unwrap(), valid_len);
+
+        // A record with a complete header, but cut short in its body
+        store.insert(b"other", b"value").unwrap();
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes.truncate(bytes.len() - 2);
+        std::fs::write(&path, &bytes).unwrap();
+
+        let mut store = Store::open(&path).unwrap();
+        store.load().unwrap();
+        assert_eq!(store.get(b"other").unwrap(), None);
+        assert_eq!(store.file_size().unwrap(), valid_len);
+
+        // New records go right after the last complete one
+        store.insert(b"other", b"again").unwrap();
+        let mut store = Store::open(&path).unwrap();
+        store.load().unwrap();
+        assert_eq!(store.get(b"key").unwrap(), Some(b"value".to_vec()));
+        assert_eq!(store.get(b"other").unwrap(), Some(b"again".to_vec()));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn compressed_values() {
+        let path = temp_store_path("compressed");
+        let mut store = Store::open(&path).unwrap();
+        let text = b"all work and no play makes jack a dull boy. ".repeat(32);
+
+        store.insert(b"plain", &text).unwrap();
+        let plain_size = store.file_size().unwrap();
+
+        store.set_compression(true);
+        store.insert(b"small", &text).unwrap();
+        let compressed_size = store.file_size().unwrap() - plain_size;
+        assert!(compressed_size < plain_size / 4);
+        store.delete(b"plain").unwrap();
+
+        // Both kinds of records read back from the same file
+        let mut store = Store::open(&path).unwrap();
+        store.load().unwrap();
+        assert_eq!(store.get(b"small").unwrap(), Some(text.clone()));
+        assert_eq!(store.get(b"plain").unwrap(), Some(vec![]));
+        assert!(store.verify().unwrap().is_empty());
+
+        let histogram = store.value_size_histogram().unwrap();
+        assert_eq!(histogram.get(&text.len()), Some(&1));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn typed_store() {
+        let path = temp_store_path("typed");
+        let mut store = TypedStore::<u32, (u64, String)>::new(Store::open(&path).unwrap());
+
+        store.insert(&1, &(42, "answer".to_string())).unwrap();
+        store.insert(&2, &(7, "days".to_string())).unwrap();
+        store.delete(&2).unwrap();
+
+        let mut store = TypedStore::<u32, (u64, String)>::new(Store::open(&path).unwrap());
+        store.store.load().unwrap();
+        assert_eq!(store.get(&1).unwrap(), Some((42, "answer".to_string())));
+        assert_eq!(store.get(&2).unwrap(), None);
+        assert_eq!(store.get(&3).unwrap(), None);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn dump_records() {
+        let path = temp_store_path("dump_records");
+        let mut store = Store::open(&path).unwrap();
+        store.insert(b"one", b"1").unwrap();
+        store.insert(b"three", b"333").unwrap();
+
+        let records = store.dump_records().unwrap();
+        assert_eq!(records.len(), 2);
+
+        let (offset, _, key_len, val_len) = records[0];
+        assert_eq!((offset, key_len, val_len), (0, 3, 1));
+
+        // Second record starts after a 12 bytes header and 4 bytes of data
+        let (offset, _, key_len, val_len) = records[1];
+        assert_eq!((offset, key_len, val_len), (16, 5, 3));
+
+        std::fs::remove_file(&path).unwrap();
+    }
 }
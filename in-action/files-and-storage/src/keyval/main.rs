@@ -39,4 +39,6 @@ fn main() {
         }
         _ => eprintln!("{}", usage),
     }
+
+    store.close().expect("Failed to close store");
 }
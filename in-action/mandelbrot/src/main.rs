@@ -1,11 +1,23 @@
 use num::complex::Complex;
+use std::io::{self, Write};
 
-fn mandelbrot_at_point(x: f64, y: f64, iterations: usize) -> usize {
+/// Points farther than 2 from the origin are known to escape
+const DEFAULT_ESCAPE_RADIUS_SQUARED: f64 = 4.0;
+
+/// Fractional iteration counts only vary continuously when points escape far enough
+const SMOOTH_ESCAPE_RADIUS_SQUARED: f64 = 256.0 * 256.0;
+
+/// @brief Counts the iterations it takes for a point to escape
+///
+/// @param escape_radius_squared Square of the distance from the origin past which
+/// a point escapes, larger values give smoother coloring
+fn mandelbrot_at_point(x: f64, y: f64, iterations: usize, escape_radius_squared: f64) -> usize {
     let mut z = Complex::new(0.0, 0.0);
     let c = Complex::new(x, y);
 
     for i in 0..iterations {
-        if z.norm() > 2.0 {
+        // Comparing squares avoids a square root at each iteration
+        if z.norm_sqr() > escape_radius_squared {
             return i;
         }
 
@@ -15,6 +27,36 @@ fn mandelbrot_at_point(x: f64, y: f64, iterations: usize) -> usize {
     return iterations;
 }
 
+/// @brief Fractional counterpart of `mandelbrot_at_point`, which varies continuously
+/// across points instead of stepping from one iteration count to the next
+///
+/// @return The normalized iteration count `i + 1 - log2(ln|z|)` of escaping points,
+/// or `iterations` for points of the set
+fn smooth_mandelbrot_at_point(
+    x: f64,
+    y: f64,
+    iterations: usize,
+    escape_radius_squared: f64,
+) -> f64 {
+    let mut z = Complex::new(0.0, 0.0);
+    let c = Complex::new(x, y);
+
+    for i in 0..iterations {
+        let norm_sqr = z.norm_sqr();
+        if norm_sqr > escape_radius_squared {
+            // ln|z| is half the logarithm of the squared norm
+            let log_norm = norm_sqr.ln() / 2.0;
+            let smooth = (i + 1) as f64 - log_norm.log2();
+            // Keep escaping points apart from those of the set
+            return smooth.clamp(0.0, (iterations - 1) as f64);
+        }
+
+        z = z * z + c;
+    }
+
+    iterations as f64
+}
+
 struct Point {
     x: f64,
     y: f64,
@@ -43,63 +85,431 @@ impl Size {
 /// @param max Top right viewport point
 /// @param size With and height of the image
 /// @param iteration Max number of iterations
+/// @param samples Number of sub-samples per pixel along each axis, whose values are
+/// averaged to smooth the edges of the set. The grid of sub-samples starts at the pixel
+/// corner, at offsets of i / samples, so it is biased towards that corner rather than
+/// centered on the pixel. With 1, only the pixel corner is sampled.
+/// @param smooth Whether to calculate fractional iteration counts for smooth coloring,
+/// instead of whole ones
 ///
 /// @return A 2D image
-fn calculate_mandelbrot(min: Point, max: Point, size: Size, iterations: usize) -> Vec<Vec<usize>> {
+fn calculate_mandelbrot(
+    min: Point,
+    max: Point,
+    size: Size,
+    iterations: usize,
+    samples: usize,
+    smooth: bool,
+) -> Vec<Vec<f64>> {
+    calculate_mandelbrot_with_progress(min, max, size, iterations, samples, smooth, |_, _| {})
+}
+
+/// @brief Calculates a Mandelbrot set, reporting progress along the way
+///
+/// @param on_row Called after each row with the number of completed rows and the total
+fn calculate_mandelbrot_with_progress(
+    min: Point,
+    max: Point,
+    size: Size,
+    iterations: usize,
+    samples: usize,
+    smooth: bool,
+    mut on_row: impl FnMut(usize, usize),
+) -> Vec<Vec<f64>> {
     let mut columns = Vec::with_capacity(size.width);
+    let count = samples * samples;
+
+    // Fractional counts for smooth coloring, or whole ones
+    let at_point = |x: f64, y: f64| {
+        if smooth {
+            smooth_mandelbrot_at_point(x, y, iterations, SMOOTH_ESCAPE_RADIUS_SQUARED)
+        } else {
+            mandelbrot_at_point(x, y, iterations, DEFAULT_ESCAPE_RADIUS_SQUARED) as f64
+        }
+    };
 
     for y in 0..size.height {
         let mut row = Vec::with_capacity(size.height);
 
         for x in 0..size.width {
-            let cx = min.x + (max.x - min.x) * (x as f64 / size.width as f64);
-            let cy = min.y + (max.y - min.y) * (y as f64 / size.height as f64);
-            let val = mandelbrot_at_point(cx, cy, iterations);
+            // Sub-samples form an evenly spaced grid within the pixel
+            let mut sum = 0.0;
+            for sy in 0..samples {
+                for sx in 0..samples {
+                    let px = x as f64 + sx as f64 / samples as f64;
+                    let py = y as f64 + sy as f64 / samples as f64;
+                    let cx = min.x + (max.x - min.x) * (px / size.width as f64);
+                    let cy = min.y + (max.y - min.y) * (py / size.height as f64);
+                    sum += at_point(cx, cy);
+                }
+            }
 
-            row.push(val);
+            // Renderers quantize the average as they need
+            row.push(sum / count as f64);
         }
 
-        columns.push(row)
+        columns.push(row);
+        on_row(y + 1, size.height);
     }
 
     columns
 }
 
+/// @brief Output target of a calculated Mandelbrot set
+trait Renderer {
+    /// @param data Iteration counts of each point, row by row, possibly fractional
+    /// @param max_iter Max number of iterations used for the calculation
+    fn render(&mut self, data: &[Vec<f64>], max_iter: usize) -> io::Result<()>;
+}
+
 /// @brief Associate an ASCII character to numeric values
-fn render_mandelbrot(mandelbrot: Vec<Vec<usize>>) {
-    for row in mandelbrot {
-        // We are going to create a line to print on terminal from a row of values
-        let mut line = String::with_capacity(row.len());
-
-        for val in row {
-            let char = match val {
-                0..=2 => ' ',
-                3..=5 => '.',
-                6..=10 => 'ø',
-                11..=30 => '*',
-                31..=100 => '+',
-                101..=200 => 'x',
-                201..=400 => '$',
-                401..=700 => '#',
-                _ => '%',
-            };
-
-            line.push(char);
+struct AsciiRenderer<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> AsciiRenderer<W> {
+    fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+impl<W: Write> Renderer for AsciiRenderer<W> {
+    fn render(&mut self, data: &[Vec<f64>], _max_iter: usize) -> io::Result<()> {
+        for row in data {
+            // We are going to create a line to print on terminal from a row of values
+            let mut line = String::with_capacity(row.len());
+
+            for &val in row {
+                // Characters map whole iteration counts
+                let char = match val.round() as usize {
+                    0..=2 => ' ',
+                    3..=5 => '.',
+                    6..=10 => 'ø',
+                    11..=30 => '*',
+                    31..=100 => '+',
+                    101..=200 => 'x',
+                    201..=400 => '$',
+                    401..=700 => '#',
+                    _ => '%',
+                };
+
+                line.push(char);
+            }
+
+            writeln!(self.writer, "{}", line)?;
         }
 
-        println!("{}", line);
+        Ok(())
+    }
+}
+
+/// Colors which iteration values go through, from fast escaping points to the slowest ones
+const PALETTE: [(u8, u8, u8); 5] = [
+    (0, 7, 100),
+    (32, 107, 203),
+    (237, 255, 255),
+    (255, 170, 0),
+    (0, 2, 0),
+];
+
+/// @brief Maps an iteration value to a color, blending between the nearest palette colors
+///
+/// Fractional values from `smooth_mandelbrot_at_point` blend without visible bands.
+/// Points which never escape are part of the set, and they are painted black.
+fn palette_color(val: f64, max_iter: usize) -> (u8, u8, u8) {
+    if val >= max_iter as f64 {
+        return (0, 0, 0);
     }
+
+    // Most points escape within a few iterations, a logarithmic scale spreads them
+    // along the palette instead of crowding its first color
+    let t = val.max(0.0).ln_1p() / (max_iter as f64).ln_1p();
+    let position = t * (PALETTE.len() - 1) as f64;
+    let index = (position as usize).min(PALETTE.len() - 2);
+    let fraction = position - index as f64;
+
+    let (from, to) = (PALETTE[index], PALETTE[index + 1]);
+    let blend = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * fraction).round() as u8;
+    (
+        blend(from.0, to.0),
+        blend(from.1, to.1),
+        blend(from.2, to.2),
+    )
 }
 
-fn main() {
-    // Calculate a mandelbrot set
-    let mandelbrot = calculate_mandelbrot(
+/// @brief Returns the ANSI escape sequence setting a 24-bit background color
+fn background_escape((r, g, b): (u8, u8, u8)) -> String {
+    format!("\x1b[48;2;{};{};{}m", r, g, b)
+}
+
+/// @brief Paints a space with a colored background for each point,
+/// for terminals supporting 24-bit colors
+struct TrueColorRenderer<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> TrueColorRenderer<W> {
+    fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+impl<W: Write> Renderer for TrueColorRenderer<W> {
+    fn render(&mut self, data: &[Vec<f64>], max_iter: usize) -> io::Result<()> {
+        for row in data {
+            let mut line = String::new();
+
+            for &val in row {
+                line.push_str(&background_escape(palette_color(val, max_iter)));
+                line.push(' ');
+            }
+
+            // Reset the background before moving to the next line
+            writeln!(self.writer, "{}\x1b[0m", line)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn main() -> io::Result<()> {
+    let iterations = 1000;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let truecolor = args.iter().any(|arg| arg == "--truecolor");
+    let progress = args.iter().any(|arg| arg == "--progress");
+    let samples = match args.iter().position(|arg| arg == "--samples") {
+        Some(i) => args
+            .get(i + 1)
+            .and_then(|samples| samples.parse().ok())
+            .filter(|&samples| samples > 0)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "--samples expects a positive number",
+                )
+            })?,
+        None => 1,
+    };
+
+    // Calculate a mandelbrot set, with fractional iteration counts for smooth colors
+    let (min, max, size) = (
         Point::new(-2.0, -1.0),
         Point::new(1.0, 1.0),
         Size::new(100, 30),
-        1000,
     );
+    let mandelbrot = if progress {
+        // Progress goes to stderr, keeping stdout for the image
+        let mandelbrot = calculate_mandelbrot_with_progress(
+            min,
+            max,
+            size,
+            iterations,
+            samples,
+            truecolor,
+            |completed, total| eprint!("\rCalculating row {}/{}", completed, total),
+        );
+        eprintln!();
+        mandelbrot
+    } else {
+        calculate_mandelbrot(min, max, size, iterations, samples, truecolor)
+    };
+
+    // Render the Mandelbrot set with ASCII characters, or colors with `--truecolor`
+    let mut renderer: Box<dyn Renderer> = if truecolor {
+        Box::new(TrueColorRenderer::new(io::stdout()))
+    } else {
+        Box::new(AsciiRenderer::new(io::stdout()))
+    };
+    renderer.render(&mandelbrot, iterations)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn escape_with_norm_sqr() {
+        // Former implementation comparing the norm against the radius
+        let at_point_with_norm = |x: f64, y: f64, iterations: usize| {
+            let mut z = Complex::new(0.0, 0.0);
+            let c = Complex::new(x, y);
+            for i in 0..iterations {
+                if z.norm() > 2.0 {
+                    return i;
+                }
+                z = z * z + c;
+            }
+            iterations
+        };
+
+        for row in 0..40 {
+            for col in 0..60 {
+                let x = -2.0 + 3.0 * col as f64 / 60.0;
+                let y = -1.0 + 2.0 * row as f64 / 40.0;
+                assert_eq!(
+                    mandelbrot_at_point(x, y, 200, DEFAULT_ESCAPE_RADIUS_SQUARED),
+                    at_point_with_norm(x, y, 200)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn smooth_iterations() {
+        // Points of the set are not affected
+        assert_eq!(
+            smooth_mandelbrot_at_point(0.0, 0.0, 100, SMOOTH_ESCAPE_RADIUS_SQUARED),
+            100.0
+        );
+
+        // Along a line outside of the set, whole counts step from one to the next,
+        // while fractional ones change by small amounts between neighboring points
+        let mut steps = 0;
+        let mut previous: Option<(usize, f64)> = None;
+        for i in 0..=1000 {
+            let x = -2.0 + i as f64 / 1000.0;
+            let count = mandelbrot_at_point(x, 1.0, 100, DEFAULT_ESCAPE_RADIUS_SQUARED);
+            let smooth = smooth_mandelbrot_at_point(x, 1.0, 100, SMOOTH_ESCAPE_RADIUS_SQUARED);
+
+            if let Some((previous_count, previous_smooth)) = previous {
+                if count != previous_count {
+                    steps += 1;
+                }
+                assert!((smooth - previous_smooth).abs() < 0.1);
+            }
+            previous = Some((count, smooth));
+        }
+        assert!(steps > 0);
+    }
 
-    // Render the Mandelbrot set with ASCII characters
-    render_mandelbrot(mandelbrot);
+    #[test]
+    fn progress() {
+        let mut calls = vec![];
+        let with_progress = calculate_mandelbrot_with_progress(
+            Point::new(-2.0, -1.0),
+            Point::new(1.0, 1.0),
+            Size::new(8, 5),
+            50,
+            1,
+            true,
+            |completed, total| calls.push((completed, total)),
+        );
+
+        assert_eq!(calls, (1..=5).map(|row| (row, 5)).collect::<Vec<_>>());
+
+        let without_progress = calculate_mandelbrot(
+            Point::new(-2.0, -1.0),
+            Point::new(1.0, 1.0),
+            Size::new(8, 5),
+            50,
+            1,
+            true,
+        );
+        assert_eq!(with_progress, without_progress);
+    }
+
+    #[test]
+    fn supersampling() {
+        // Former implementation sampling only the corner of each pixel
+        let (width, height) = (30, 20);
+        let expected: Vec<Vec<f64>> = (0..height)
+            .map(|y| {
+                (0..width)
+                    .map(|x| {
+                        let cx = -2.0 + 3.0 * (x as f64 / width as f64);
+                        let cy = -1.0 + 2.0 * (y as f64 / height as f64);
+                        mandelbrot_at_point(cx, cy, 100, DEFAULT_ESCAPE_RADIUS_SQUARED) as f64
+                    })
+                    .collect()
+            })
+            .collect();
+        let single = calculate_mandelbrot(
+            Point::new(-2.0, -1.0),
+            Point::new(1.0, 1.0),
+            Size::new(width, height),
+            100,
+            1,
+            false,
+        );
+        assert_eq!(single, expected);
+
+        // A single pixel covering both points of the set and points escaping at once
+        let sub_samples = [(-2.0, -1.0), (-0.5, -1.0), (-2.0, 0.0), (-0.5, 0.0)]
+            .iter()
+            .map(|&(x, y)| mandelbrot_at_point(x, y, 100, DEFAULT_ESCAPE_RADIUS_SQUARED) as f64)
+            .collect::<Vec<_>>();
+        let average = sub_samples.iter().sum::<f64>() / 4.0;
+
+        let sampled = calculate_mandelbrot(
+            Point::new(-2.0, -1.0),
+            Point::new(1.0, 1.0),
+            Size::new(1, 1),
+            100,
+            2,
+            false,
+        );
+        assert_eq!(sampled, vec![vec![average]]);
+        assert!(average > sub_samples.iter().cloned().fold(f64::MAX, f64::min));
+        assert!(average < sub_samples.iter().cloned().fold(0.0, f64::max));
+
+        // Fractional counts are averaged without rounding
+        let smooth_samples = [(-2.0, -1.0), (-0.5, -1.0), (-2.0, 0.0), (-0.5, 0.0)]
+            .iter()
+            .map(|&(x, y)| smooth_mandelbrot_at_point(x, y, 100, SMOOTH_ESCAPE_RADIUS_SQUARED))
+            .sum::<f64>();
+        let sampled = calculate_mandelbrot(
+            Point::new(-2.0, -1.0),
+            Point::new(1.0, 1.0),
+            Size::new(1, 1),
+            100,
+            2,
+            true,
+        );
+        assert_eq!(sampled, vec![vec![smooth_samples / 4.0]]);
+        assert_ne!(sampled[0][0].fract(), 0.0);
+    }
+
+    #[test]
+    fn ascii_renderer() {
+        let data = vec![vec![0.0, 4.0, 8.0], vec![20.0, 50.0, 1000.0]];
+
+        let mut buffer = vec![];
+        AsciiRenderer::new(&mut buffer).render(&data, 1000).unwrap();
+
+        assert_eq!(String::from_utf8(buffer).unwrap(), " .ø\n*+%\n");
+    }
+
+    #[test]
+    fn palette_colors() {
+        assert_eq!(background_escape((255, 0, 32)), "\x1b[48;2;255;0;32m");
+
+        assert_eq!(palette_color(0.0, 1000), PALETTE[0]);
+        assert_eq!(palette_color(1000.0, 1000), (0, 0, 0));
+        // Right before the set, the last palette color is reached
+        assert_eq!(palette_color(999.0, 1000), PALETTE[PALETTE.len() - 1]);
+
+        // Halfway on a logarithmic scale lands on the middle color
+        assert_eq!(palette_color(10.0, 120), PALETTE[2]);
+
+        // Fractional values blend between those of whole iteration counts
+        let (from, to) = (palette_color(1.0, 1000), palette_color(2.0, 1000));
+        let between = palette_color(1.5, 1000);
+        assert!(between != from && between != to);
+        assert!(between.2 > from.2 && between.2 < to.2);
+    }
+
+    #[test]
+    fn truecolor_renderer() {
+        let data = vec![vec![0.0, 10.0]];
+
+        let mut buffer = vec![];
+        TrueColorRenderer::new(&mut buffer)
+            .render(&data, 10)
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "\x1b[48;2;0;7;100m \x1b[48;2;0;0;0m \x1b[0m\n"
+        );
+    }
 }
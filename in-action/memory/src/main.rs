@@ -1,14 +1,32 @@
 use std::io::Read;
 
+/// Address of a value, casting the raw pointer to an integer
+fn address_of<T>(value: &T) -> usize {
+    value as *const T as usize
+}
+
+/// Size and alignment in bytes of a type
+fn size_and_align<T>() -> (usize, usize) {
+    (std::mem::size_of::<T>(), std::mem::align_of::<T>())
+}
+
 fn pointers() {
     // We can create raw pointers by coercing references
     let a = 42;
     let a_ptr = &a as *const i32;
-    // It is unsafe to access a pointer value
-    // But we need it if we want to print the address of a's last byte
-    let a_addr: usize = unsafe { std::mem::transmute(a_ptr) };
+    // Casting a pointer to an integer is safe, only dereferencing it is not.
+    // We need it if we want to print the address of a's last byte
+    let a_addr = address_of(&a);
+    let (a_size, a_align) = size_and_align::<i32>();
 
-    println!("a: {} ({:p}...0x{:x})", a, a_ptr, a_addr + 3);
+    println!(
+        "a: {} ({:p}...0x{:x}) size {} align {}",
+        a,
+        a_ptr,
+        a_addr + a_size - 1,
+        a_size,
+        a_align
+    );
 }
 
 fn stack_and_heap() {
@@ -57,8 +75,163 @@ fn inspection() {
     println!("{:?}", process);
 }
 
+/// A region of the virtual memory of a process
+#[cfg(target_os = "linux")]
+#[derive(Debug)]
+struct MemRegion {
+    start: usize,
+    end: usize,
+    /// Like `r-xp`, readable, not writable, executable, and private
+    perms: String,
+    /// File mapped in this region, or a pseudo-path like `[heap]`
+    path: Option<String>,
+}
+
+#[cfg(target_os = "linux")]
+impl MemRegion {
+    fn is_readable(&self) -> bool {
+        self.perms.starts_with('r')
+    }
+
+    fn is_writable(&self) -> bool {
+        self.perms.chars().nth(1) == Some('w')
+    }
+
+    fn is_executable(&self) -> bool {
+        self.perms.chars().nth(2) == Some('x')
+    }
+}
+
+/// Parses a line like `<start>-<end> <perms> <offset> <dev> <inode> [path]`
+#[cfg(target_os = "linux")]
+fn parse_map_line(line: &str) -> std::io::Result<MemRegion> {
+    let invalid = || {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Invalid memory map line: {}", line),
+        )
+    };
+
+    // The path is the rest of the line, as it may contain spaces or end with ` (deleted)`
+    let mut fields = line.splitn(6, char::is_whitespace);
+    let range = fields.next().ok_or_else(invalid)?;
+    let perms = fields.next().ok_or_else(invalid)?;
+    // Skip offset, device, and inode
+    let path = fields
+        .nth(3)
+        .map(str::trim)
+        .filter(|path| !path.is_empty())
+        .map(String::from);
+
+    let (start, end) = range.split_once('-').ok_or_else(invalid)?;
+    let start = usize::from_str_radix(start, 16).map_err(|_| invalid())?;
+    let end = usize::from_str_radix(end, 16).map_err(|_| invalid())?;
+
+    Ok(MemRegion {
+        start,
+        end,
+        perms: String::from(perms),
+        path,
+    })
+}
+
+/// Reads the memory regions of this process from /proc/self/maps
+#[cfg(target_os = "linux")]
+fn read_maps() -> std::io::Result<Vec<MemRegion>> {
+    let maps = std::fs::read_to_string("/proc/self/maps")?;
+    maps.lines().map(parse_map_line).collect()
+}
+
+#[cfg(target_os = "linux")]
+fn memory_map() {
+    let regions = read_maps().expect("Failed to read memory map");
+
+    let size: usize = regions.iter().map(|region| region.end - region.start).sum();
+    let readable = regions.iter().filter(|region| region.is_readable()).count();
+    let writable = regions.iter().filter(|region| region.is_writable()).count();
+    let executable = regions
+        .iter()
+        .filter(|region| region.is_executable())
+        .count();
+
+    println!(
+        "{} regions ({} bytes): {} readable, {} writable, {} executable",
+        regions.len(),
+        size,
+        readable,
+        writable,
+        executable
+    );
+
+    if let Some(heap) = regions
+        .iter()
+        .find(|region| region.path.as_deref() == Some("[heap]"))
+    {
+        println!("heap: 0x{:x}...0x{:x}", heap.start, heap.end);
+    }
+}
+
 fn main() {
     pointers();
     stack_and_heap();
     inspection();
+    #[cfg(target_os = "linux")]
+    memory_map();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn introspection() {
+        assert_eq!(size_and_align::<u32>(), (4, 4));
+        assert_eq!(size_and_align::<u8>(), (1, 1));
+        assert_eq!(size_and_align::<[u16; 3]>(), (6, 2));
+
+        let a = 1;
+        let b = 2;
+        assert_ne!(address_of(&a), address_of(&b));
+        assert_eq!(address_of(&a), &a as *const i32 as usize);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn map_line() {
+        let region =
+            parse_map_line("55d0c8a00000-55d0c8a21000 r-xp 00002000 08:01 1234  /usr/bin/memory")
+                .unwrap();
+        assert_eq!(region.start, 0x55d0c8a00000);
+        assert_eq!(region.end, 0x55d0c8a21000);
+        assert!(region.is_readable() && !region.is_writable() && region.is_executable());
+        assert_eq!(region.path.as_deref(), Some("/usr/bin/memory"));
+
+        // Anonymous regions have no path
+        let region = parse_map_line("7ffd0000-7ffd1000 rw-p 00000000 00:00 0").unwrap();
+        assert_eq!(region.path, None);
+        let region = parse_map_line("7ffd0000-7ffd1000 rw-p 00000000 00:00 0    ").unwrap();
+        assert_eq!(region.path, None);
+
+        // Spaces within the path are kept
+        let region = parse_map_line(
+            "7f00a000-7f00b000 r--p 00000000 08:01 5678                       /tmp/my file.so (deleted)",
+        )
+        .unwrap();
+        assert_eq!(region.path.as_deref(), Some("/tmp/my file.so (deleted)"));
+
+        assert!(parse_map_line("invalid").is_err());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn executable_region() {
+        let regions = read_maps().unwrap();
+        assert!(regions.iter().any(|region| region.is_executable()));
+
+        // The code of this function is in one of the executable regions
+        let code = executable_region as fn() as usize;
+        assert!(regions
+            .iter()
+            .any(|region| region.is_executable() && (region.start..region.end).contains(&code)));
+    }
 }
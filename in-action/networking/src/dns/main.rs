@@ -1,15 +1,129 @@
+#[macro_use]
+extern crate serde_derive;
+
 use std::{
+    io,
     net::{SocketAddr, UdpSocket},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use clap::{Command, Arg};
 use trust_dns_resolver::proto::{
-    op::{Message, MessageType, OpCode, Query},
+    op::{Edns, Message, MessageType, OpCode, Query},
     rr::RecordType,
     serialize::binary::{BinEncodable, BinEncoder},
 };
 
+/// UDP payload size advertised through EDNS0, instead of the classic 512 bytes
+const EDNS_PAYLOAD_SIZE: u16 = 4096;
+
+/// An answer record in the JSON output
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Answer {
+    name: String,
+    #[serde(rename = "type")]
+    record_type: String,
+    ttl: u32,
+    /// Textual form of the record data, such as an IP address or a domain name
+    data: Option<String>,
+}
+
+/// Returns the answers of a response as a JSON object with an `answers` array
+fn answers_to_json(response: &Message) -> String {
+    let answers: Vec<Answer> = response
+        .answers()
+        .iter()
+        .map(|answer| Answer {
+            name: answer.name().to_string(),
+            record_type: answer.record_type().to_string(),
+            ttl: answer.ttl(),
+            data: answer.data().map(|data| data.to_string()),
+        })
+        .collect();
+
+    serde_json::json!({ "answers": answers }).to_string()
+}
+
+/// Encodes a query message for the A records of a domain name,
+/// with an OPT pseudo-record advertising a larger UDP payload size
+fn build_query(domain_name: trust_dns_resolver::Name, payload_size: u16) -> Vec<u8> {
+    let mut edns = Edns::new();
+    edns.set_max_payload(payload_size);
+
+    // Define message
+    let mut msg = Message::new();
+    msg.set_id(rand::random::<u16>())
+        .set_message_type(MessageType::Query)
+        .add_query(Query::query(domain_name, RecordType::A))
+        .set_op_code(OpCode::Query)
+        .set_recursion_desired(true)
+        .set_edns(edns);
+
+    // Encode message to a buffer of byte
+    let mut buffer = Vec::with_capacity(512);
+    let mut encoder = BinEncoder::new(&mut buffer);
+    msg.emit(&mut encoder).unwrap();
+    buffer
+}
+
+/// Carries an encoded query to a DNS server, returning the encoded response
+trait DnsTransport {
+    fn query(&self, wire: &[u8]) -> io::Result<Vec<u8>>;
+}
+
+/// Transport sending queries to a server through a UDP socket
+struct UdpTransport {
+    socket: UdpSocket,
+    server: SocketAddr,
+}
+
+impl UdpTransport {
+    fn new(server: SocketAddr, timeout: Duration) -> io::Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.set_read_timeout(Some(timeout))?;
+        socket.set_nonblocking(false)?;
+        Ok(Self { socket, server })
+    }
+}
+
+impl DnsTransport for UdpTransport {
+    fn query(&self, wire: &[u8]) -> io::Result<Vec<u8>> {
+        self.socket.send_to(wire, self.server)?;
+
+        let mut response = vec![0; EDNS_PAYLOAD_SIZE as usize];
+        let (len, _) = self.socket.recv_from(&mut response)?;
+        response.truncate(len);
+        Ok(response)
+    }
+}
+
+/// Queries the A records of a domain name, checking the response answers this query
+fn resolve(
+    transport: &impl DnsTransport,
+    domain_name: trust_dns_resolver::Name,
+) -> io::Result<Message> {
+    let query = build_query(domain_name, EDNS_PAYLOAD_SIZE);
+    let response = transport.query(&query)?;
+
+    let invalid = |err| io::Error::new(io::ErrorKind::InvalidData, err);
+    let response = Message::from_vec(&response).map_err(invalid)?;
+
+    // The first two bytes of the query hold its id
+    let id = u16::from_be_bytes([query[0], query[1]]);
+    if response.id() != id {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Response id {} does not match query id {}",
+                response.id(),
+                id
+            ),
+        ));
+    }
+
+    Ok(response)
+}
+
 fn main() {
     let matches = Command::new("dns")
         .version("0.2")
@@ -21,6 +135,11 @@ fn main() {
                 .default_value("1.1.1.1"),
         )
         .arg(Arg::new("domain-name").required(true))
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .help("Print the answers as JSON"),
+        )
         .get_matches();
 
     let dns_server = matches.value_of("dns-server").unwrap();
@@ -28,38 +147,22 @@ fn main() {
     let domain_name = matches.value_of("domain-name").unwrap();
     let domain_name = trust_dns_resolver::Name::from_ascii(&domain_name).unwrap();
 
-    // Define message
-    let mut msg = Message::new();
-    msg.set_id(rand::random::<u16>())
-        .set_message_type(MessageType::Query)
-        .add_query(Query::query(domain_name, RecordType::A))
-        .set_op_code(OpCode::Query)
-        .set_recursion_desired(true);
-
-    // Encode message to a buffer of byte
-    let mut buffer = Vec::with_capacity(512);
-    let mut encoder = BinEncoder::new(&mut buffer);
-    msg.emit(&mut encoder).unwrap();
-
-    // Listening socket
-    let localhost = UdpSocket::bind("0.0.0.0:0").expect("Failed to bind to local socket");
-    let timeout = Duration::from_secs(3);
-    localhost.set_read_timeout(Some(timeout)).unwrap();
-    localhost.set_nonblocking(false).unwrap();
-
     let dns_server: SocketAddr = format!("{}:53", dns_server)
         .parse()
         .expect("Failed to parse dns server address");
-    let _ = localhost
-        .send_to(&buffer, dns_server)
-        .expect("Failed to send request");
 
-    let mut response: [u8; 512] = [0; 512];
-    let (_, _) = localhost
-        .recv_from(&mut response)
-        .expect("Failed to recieve response");
+    let transport = UdpTransport::new(dns_server, Duration::from_secs(3))
+        .expect("Failed to bind to local socket");
+
+    // Time the round trip of the query
+    let start = Instant::now();
+    let dns_response = resolve(&transport, domain_name).expect("Failed to resolve domain name");
+    let query_time = start.elapsed();
 
-    let dns_response = Message::from_vec(&response).expect("Failed to parse response");
+    if matches.is_present("json") {
+        println!("{}", answers_to_json(&dns_response));
+        return;
+    }
 
     for answer in dns_response.answers() {
         if answer.record_type() == RecordType::A {
@@ -68,4 +171,117 @@ fn main() {
             println!("{}", ip.to_string());
         }
     }
+
+    // Summary in the style of dig, after the answers
+    println!();
+    println!(";; Query time: {} ms", query_time.as_millis());
+    println!(";; SERVER: {}", dns_server);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn query_with_edns() {
+        let domain_name = trust_dns_resolver::Name::from_ascii("example.com").unwrap();
+        let buffer = build_query(domain_name, 4096);
+
+        // One additional record
+        assert_eq!(&buffer[10..12], &[0, 1]);
+
+        // OPT record at the end: root name, type 41, payload size as class,
+        // then extended rcode, version, flags and empty data
+        assert_eq!(
+            &buffer[buffer.len() - 11..],
+            &[0, 0, 41, 0x10, 0x00, 0, 0, 0, 0, 0, 0]
+        );
+
+        let msg = Message::from_vec(&buffer).unwrap();
+        assert_eq!(msg.edns().unwrap().max_payload(), 4096);
+    }
+
+    /// Replies with a canned response, given the id of the query
+    struct MockTransport {
+        response: Vec<u8>,
+    }
+
+    impl DnsTransport for MockTransport {
+        fn query(&self, wire: &[u8]) -> io::Result<Vec<u8>> {
+            let mut response = self.response.clone();
+            response[..2].copy_from_slice(&wire[..2]);
+            Ok(response)
+        }
+    }
+
+    #[test]
+    fn resolve_with_mock_transport() {
+        // Captured response for the A record of example.com
+        #[rustfmt::skip]
+        let response = vec![
+            // Id, flags (response, recursion desired and available), one question, one answer
+            0x00, 0x00, 0x81, 0x80, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00,
+            // Question: example.com, type A, class IN
+            0x07, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0x03, b'c', b'o', b'm', 0x00,
+            0x00, 0x01, 0x00, 0x01,
+            // Answer: pointer to the question name, type A, class IN, TTL 3600, 4 bytes of data
+            0xc0, 0x0c, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10, 0x00, 0x04,
+            93, 184, 216, 34,
+        ];
+        let transport = MockTransport { response };
+
+        let domain_name = trust_dns_resolver::Name::from_ascii("example.com").unwrap();
+        let response = resolve(&transport, domain_name).unwrap();
+
+        let answers = response.answers();
+        assert_eq!(answers.len(), 1);
+        assert_eq!(answers[0].record_type(), RecordType::A);
+        assert_eq!(answers[0].ttl(), 3600);
+        assert_eq!(
+            answers[0].data().unwrap().to_ip_addr(),
+            Some("93.184.216.34".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn json_answers() {
+        use std::net::Ipv4Addr;
+        use trust_dns_resolver::proto::rr::{RData, Record};
+
+        let name = trust_dns_resolver::Name::from_ascii("www.example.com.").unwrap();
+        let target = trust_dns_resolver::Name::from_ascii("example.com.").unwrap();
+
+        let mut response = Message::new();
+        response.add_answer(Record::from_rdata(
+            name.clone(),
+            300,
+            RData::CNAME(target.clone()),
+        ));
+        response.add_answer(Record::from_rdata(
+            target,
+            60,
+            RData::A(Ipv4Addr::new(93, 184, 216, 34)),
+        ));
+
+        let json: serde_json::Value = serde_json::from_str(&answers_to_json(&response)).unwrap();
+        let answers: Vec<Answer> = serde_json::from_value(json["answers"].clone()).unwrap();
+
+        assert_eq!(
+            answers,
+            vec![
+                Answer {
+                    name: String::from("www.example.com."),
+                    record_type: String::from("CNAME"),
+                    ttl: 300,
+                    data: Some(String::from("example.com.")),
+                },
+                Answer {
+                    name: String::from("example.com."),
+                    record_type: String::from("A"),
+                    ttl: 60,
+                    data: Some(String::from("93.184.216.34")),
+                },
+            ]
+        );
+    }
 }
@@ -1,16 +1,129 @@
+use std::io::Write;
+use std::time::Duration;
+
+use clap::{Arg, Command};
 use futures::executor::block_on;
+use retry::with_retry;
+
+/// Writes the chunks returned by `next_chunk` as they arrive, until there are no more
+/// or `max_bytes` have been written, returning how many bytes were written
+fn stream_body<C, W>(
+    mut next_chunk: impl FnMut() -> Result<Option<C>, Box<dyn std::error::Error>>,
+    writer: &mut W,
+    max_bytes: Option<usize>,
+) -> Result<usize, Box<dyn std::error::Error>>
+where
+    C: AsRef<[u8]>,
+    W: Write,
+{
+    let max_bytes = max_bytes.unwrap_or(usize::MAX);
+    let mut written = 0;
+
+    while written < max_bytes {
+        let chunk = match next_chunk()? {
+            Some(chunk) => chunk,
+            None => break,
+        };
+
+        // The last chunk may go past the limit
+        let chunk = chunk.as_ref();
+        let len = chunk.len().min(max_bytes - written);
+        writer.write_all(&chunk[..len])?;
+        written += len;
+    }
+
+    writer.flush()?;
+    Ok(written)
+}
 
 /// Using the reqwest library
-fn http() -> Result<(), Box<dyn std::error::Error>> {
-    let content = block_on(reqwest::get("https://www.antoniocaggiano.eu"))?;
-    let content = block_on(content.text())?;
-    for line in content.split('\n') {
-        println!("{}", line);
+fn http(
+    url: &str,
+    max_bytes: Option<usize>,
+    lines: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Transient failures, like a connection reset, are retried a few times
+    let mut content = with_retry(3, Duration::from_millis(500), || {
+        block_on(reqwest::get(url))
+    })?;
+
+    if lines {
+        // Buffer the whole body, then print it line by line
+        let content = block_on(content.text())?;
+        for line in content.split('\n') {
+            println!("{}", line);
+        }
+        return Ok(());
     }
+
+    // Stream the body, so that it is never kept in memory as a whole
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+    stream_body(|| Ok(block_on(content.chunk())?), &mut stdout, max_bytes)?;
     Ok(())
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    http()?;
+    let matches = Command::new("http")
+        .about("HTTP client streaming responses to stdout")
+        .arg(Arg::new("url").default_value("https://www.antoniocaggiano.eu"))
+        .arg(
+            Arg::new("max-bytes")
+                .long("max-bytes")
+                .takes_value(true)
+                .help("Stops after writing this number of bytes of the body"),
+        )
+        .arg(
+            Arg::new("lines")
+                .long("lines")
+                .help("Reads the whole body before printing it line by line"),
+        )
+        .get_matches();
+
+    let url = matches.value_of("url").unwrap();
+    let max_bytes = match matches.value_of("max-bytes") {
+        Some(max_bytes) => Some(max_bytes.parse()?),
+        None => None,
+    };
+
+    http(url, max_bytes, matches.is_present("lines"))?;
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Returns a chunk source yielding each of the chunks in order
+    fn chunks(
+        chunks: &'static [&'static str],
+    ) -> impl FnMut() -> Result<Option<&'static str>, Box<dyn std::error::Error>> {
+        let mut chunks = chunks.iter().copied();
+        move || Ok(chunks.next())
+    }
+
+    #[test]
+    fn stream_all_chunks() {
+        let mut out = vec![];
+        let written = stream_body(chunks(&["hello", " ", "world"]), &mut out, None).unwrap();
+        assert_eq!(written, 11);
+        assert_eq!(out, b"hello world");
+    }
+
+    #[test]
+    fn stream_up_to_max_bytes() {
+        let mut requested = 0;
+        let mut source = chunks(&["hello", " ", "world"]);
+        let counting_source = || {
+            requested += 1;
+            source()
+        };
+
+        let mut out = vec![];
+        let written = stream_body(counting_source, &mut out, Some(8)).unwrap();
+        assert_eq!(written, 8);
+        assert_eq!(out, b"hello wo");
+        // No chunk is requested once the limit is reached
+        assert_eq!(requested, 3);
+    }
+}
@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+/// Calls `op` until it succeeds, up to `attempts` times, returning the last error otherwise.
+/// After each failure it sleeps, starting from `base_delay` and doubling every time.
+pub fn with_retry<T, E>(
+    attempts: usize,
+    base_delay: Duration,
+    mut op: impl FnMut() -> Result<T, E>,
+) -> Result<T, E> {
+    let mut delay = base_delay;
+
+    // There is always at least one attempt
+    for _ in 1..attempts {
+        match op() {
+            Ok(value) => return Ok(value),
+            Err(_) => {
+                std::thread::sleep(delay);
+                delay *= 2;
+            }
+        }
+    }
+
+    op()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Returns an operation which fails `failures` times before succeeding,
+    /// counting how many times it is called
+    fn flaky(failures: usize, calls: &mut usize) -> impl FnMut() -> Result<usize, String> + '_ {
+        move || {
+            *calls += 1;
+            if *calls <= failures {
+                Err(format!("failure {}", calls))
+            } else {
+                Ok(*calls)
+            }
+        }
+    }
+
+    #[test]
+    fn succeed_after_failures() {
+        let mut calls = 0;
+        let result = with_retry(3, Duration::ZERO, flaky(2, &mut calls));
+        assert_eq!(result, Ok(3));
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn stop_at_first_success() {
+        let mut calls = 0;
+        let result = with_retry(5, Duration::ZERO, flaky(0, &mut calls));
+        assert_eq!(result, Ok(1));
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn give_up_after_attempts() {
+        let mut calls = 0;
+        let result = with_retry(3, Duration::ZERO, flaky(5, &mut calls));
+        assert_eq!(result, Err(String::from("failure 3")));
+        assert_eq!(calls, 3);
+    }
+}
@@ -1,23 +1,59 @@
-use std::{io::Write, net::TcpStream};
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{IpAddr, TcpStream},
+};
 
+use clap::{Arg, Command};
 use openssl::ssl::{Ssl, SslContext, SslMethod, SslStream};
 
-/// Using OpenSSL and TCP from the standard library tools
-fn tcp() -> std::io::Result<()> {
-    let ctx_builder =
-        SslContext::builder(SslMethod::tls()).expect("Failed to create builder");
+/// Removes the square brackets around an IPv6 literal, as in `[::1]`
+fn strip_brackets(host: &str) -> &str {
+    host.strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host)
+}
+
+/// Returns the host as it goes in the `Host` header, where IPv6 literals need brackets
+fn host_header(host: &str) -> String {
+    match host.parse() {
+        Ok(IpAddr::V6(ip)) => format!("[{}]", ip),
+        _ => host.to_string(),
+    }
+}
+
+/// Wraps an OpenSSL error into an I/O error
+fn ssl_error(err: impl std::fmt::Display) -> std::io::Error {
+    std::io::Error::other(err.to_string())
+}
+
+/// Opens a TLS connection to a host, which can be a name or an IPv4 or IPv6 literal
+fn connect(host: &str, port: u16) -> std::io::Result<SslStream<TcpStream>> {
+    let ctx_builder = SslContext::builder(SslMethod::tls()).map_err(ssl_error)?;
     let ctx = ctx_builder.build();
 
-    let mut ssl = Ssl::new(&ctx).expect("Failed to create Ssl");
+    let mut ssl = Ssl::new(&ctx).map_err(ssl_error)?;
     ssl.set_connect_state();
 
-    let connection = TcpStream::connect("www.antoniocaggiano.eu:443")?;
-    let mut ssl_stream = SslStream::new(ssl, connection).expect("failed to create SslStream");
-    ssl_stream.do_handshake().expect("Failed to do handshake");
+    // Server Name Indication tells servers hosting multiple sites which certificate to use,
+    // while IP literals are not valid names to indicate
+    if host.parse::<IpAddr>().is_err() {
+        ssl.set_hostname(host).map_err(ssl_error)?;
+    }
+
+    let connection = TcpStream::connect((host, port))?;
+    let mut ssl_stream = SslStream::new(ssl, connection).map_err(ssl_error)?;
+    ssl_stream.do_handshake().map_err(ssl_error)?;
+
+    Ok(ssl_stream)
+}
+
+/// Using OpenSSL and TCP from the standard library tools
+fn tcp(host: &str, port: u16) -> std::io::Result<()> {
+    let mut ssl_stream = connect(host, port)?;
 
     ssl_stream.write_all(b"GET / HTTP/1.0")?;
     ssl_stream.write_all(b"\r\n")?;
-    ssl_stream.write_all(b"Host: www.antoniocaggiano.eu")?;
+    ssl_stream.write_all(format!("Host: {}", host_header(host)).as_bytes())?;
     ssl_stream.write_all(b"\r\n\r\n")?;
 
     std::io::copy(&mut ssl_stream, &mut std::io::stdout())?;
@@ -25,7 +61,253 @@ fn tcp() -> std::io::Result<()> {
     Ok(())
 }
 
+/// HTTP response with its status line and headers kept as text
+struct Response {
+    head: String,
+    body: Vec<u8>,
+}
+
+fn invalid_data(message: impl Into<String>) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.into())
+}
+
+/// Reads a body sent with `Transfer-Encoding: chunked`, where each chunk is preceded
+/// by its size in hexadecimal, and a chunk of size zero ends the body
+fn read_chunked_body<R: BufRead>(reader: &mut R) -> std::io::Result<Vec<u8>> {
+    let mut body = vec![];
+
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+
+        // Chunk extensions follow the size after a semicolon
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16)
+            .map_err(|_| invalid_data(format!("Invalid chunk size {:?}", size)))?;
+
+        if size == 0 {
+            break;
+        }
+
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader.read_exact(&mut body[start..])?;
+
+        let mut end = [0; 2];
+        reader.read_exact(&mut end)?;
+        if &end != b"\r\n" {
+            return Err(invalid_data("Chunk not followed by CRLF"));
+        }
+    }
+
+    // Optional trailer headers end with an empty line
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        if line == "\r\n" {
+            return Ok(body);
+        }
+    }
+}
+
+/// Reads a response delimited by its `Content-Length` or chunked encoding,
+/// so the connection can be reused.
+/// Returns `None` when the server has closed the connection.
+fn read_response<R: BufRead>(reader: &mut R) -> std::io::Result<Option<Response>> {
+    let mut head = String::new();
+    let mut content_length = None;
+    let mut chunked = false;
+
+    // Status line and headers end with an empty line
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            if head.is_empty() {
+                return Ok(None);
+            }
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+
+        if line == "\r\n" {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse::<usize>().ok();
+            } else if name.eq_ignore_ascii_case("transfer-encoding") {
+                chunked = value.trim().eq_ignore_ascii_case("chunked");
+            }
+        }
+        head.push_str(&line);
+    }
+
+    // Informational, No Content, and Not Modified responses never have a body
+    let status = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse::<u16>().ok())
+        .ok_or_else(|| invalid_data("Invalid status line"))?;
+    let has_body = !(100..200).contains(&status) && status != 204 && status != 304;
+
+    let body = if !has_body {
+        vec![]
+    } else if chunked {
+        read_chunked_body(reader)?
+    } else if let Some(len) = content_length {
+        let mut body = vec![0; len];
+        reader.read_exact(&mut body)?;
+        body
+    } else {
+        // Without a length, the body lasts until the server closes the connection
+        let mut body = vec![];
+        reader.read_to_end(&mut body)?;
+        body
+    };
+
+    Ok(Some(Response { head, body }))
+}
+
+/// Sends a request for each path read from stdin over the same connection
+fn keep_alive(host: &str, port: u16) -> std::io::Result<()> {
+    let mut reader = BufReader::new(connect(host, port)?);
+
+    println!(
+        "Connected to {}:{}, enter a path for each request",
+        host, port
+    );
+
+    for path in std::io::stdin().lock().lines() {
+        let path = path?;
+        let path = path.trim();
+        if path.is_empty() {
+            continue;
+        }
+
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: keep-alive\r\n\r\n",
+            path,
+            host_header(host)
+        );
+        reader.get_mut().write_all(request.as_bytes())?;
+
+        match read_response(&mut reader)? {
+            Some(response) => {
+                print!("{}\r\n", response.head);
+                std::io::stdout().write_all(&response.body)?;
+                println!();
+            }
+            None => {
+                println!("Connection closed by {}", host);
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tcp()?;
+    let matches = Command::new("tcp")
+        .about("HTTPS over TCP")
+        .arg(Arg::new("host").default_value("www.antoniocaggiano.eu"))
+        .arg(Arg::new("port").default_value("443"))
+        .arg(
+            Arg::new("interactive")
+                .short('i')
+                .long("interactive")
+                .help("Keeps the connection alive for multiple requests"),
+        )
+        .get_matches();
+
+    let host = strip_brackets(matches.value_of("host").unwrap());
+    let port = matches.value_of("port").unwrap().parse()?;
+
+    let result = if matches.is_present("interactive") {
+        keep_alive(host, port)
+    } else {
+        tcp(host, port)
+    };
+
+    if let Err(err) = result {
+        eprintln!("Failed to talk to {}:{}: {}", host_header(host), port, err);
+        std::process::exit(1);
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn read_responses() {
+        let stream = "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello\
+            HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\n\r\n";
+        let mut reader = std::io::Cursor::new(stream);
+
+        let response = read_response(&mut reader).unwrap().unwrap();
+        assert!(response.head.starts_with("HTTP/1.1 200 OK"));
+        assert_eq!(response.body, b"hello");
+
+        let response = read_response(&mut reader).unwrap().unwrap();
+        assert!(response.head.starts_with("HTTP/1.1 404"));
+        assert!(response.body.is_empty());
+
+        // The server closed the connection
+        assert!(read_response(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn read_chunked_responses() {
+        let stream = "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+            5\r\nhello\r\n7;name=value\r\n, world\r\n0\r\nExpires: never\r\n\r\n\
+            HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+        let mut reader = std::io::Cursor::new(stream);
+
+        let response = read_response(&mut reader).unwrap().unwrap();
+        assert_eq!(response.body, b"hello, world");
+
+        // The next response on the connection is still readable
+        let response = read_response(&mut reader).unwrap().unwrap();
+        assert_eq!(response.body, b"ok");
+
+        let mut reader =
+            std::io::Cursor::new("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n");
+        let err = read_response(&mut reader).err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn read_responses_without_body() {
+        let stream = "HTTP/1.1 100 Continue\r\n\r\n\
+            HTTP/1.1 204 No Content\r\n\r\n\
+            HTTP/1.1 304 Not Modified\r\nContent-Length: 10\r\n\r\n\
+            HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+        let mut reader = std::io::Cursor::new(stream);
+
+        for status in ["100", "204", "304"].iter() {
+            let response = read_response(&mut reader).unwrap().unwrap();
+            assert!(response.head.contains(status));
+            assert!(response.body.is_empty());
+        }
+
+        let response = read_response(&mut reader).unwrap().unwrap();
+        assert_eq!(response.body, b"ok");
+    }
+
+    #[test]
+    fn ipv6_hosts() {
+        assert_eq!(strip_brackets("[::1]"), "::1");
+        assert_eq!(strip_brackets("::1"), "::1");
+        assert_eq!(strip_brackets("example.com"), "example.com");
+
+        assert_eq!(host_header("::1"), "[::1]");
+        assert_eq!(host_header("127.0.0.1"), "127.0.0.1");
+        assert_eq!(host_header("example.com"), "example.com");
+    }
+}
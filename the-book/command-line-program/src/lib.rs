@@ -1,71 +1,620 @@
+use std::borrow::Cow;
 use std::env;
 use std::error::Error;
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+/// How the query should match a line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchMode {
+    /// The query can appear anywhere in the line
+    Substring,
+    /// The query must be bounded by non-word characters or the line edges
+    Word,
+    /// The whole trimmed line must be equal to the query
+    Line,
+}
+
+/// Which file paths to print instead of the matching lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListFiles {
+    /// Files with at least one matching line
+    WithMatches,
+    /// Files without any matching line
+    WithoutMatch,
+}
+
+/// Reasons a configuration can not be built
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    /// No query to search for
+    MissingQuery,
+    /// No file to search in
+    MissingFilePath,
+    /// The flag is missing its value, or the value is not valid
+    InvalidFlag(String),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::MissingQuery => write!(f, "Can not get query string"),
+            ConfigError::MissingFilePath => write!(f, "Can not get file name"),
+            ConfigError::InvalidFlag(flag) => write!(f, "Invalid value for {}", flag),
+        }
+    }
+}
+
+impl Error for ConfigError {}
 
 pub struct Config {
-    query: String,
+    // A line matches if it matches any of these
+    queries: Vec<String>,
     file_path: String,
     case_sensitive: bool,
+    // Stop searching after this number of matching lines
+    max_count: Option<usize>,
+    mode: MatchMode,
+    // Highlight matches with ANSI escape codes
+    color: bool,
+    // Search all files within the directory at file path
+    recursive: bool,
+    // Print file paths only
+    list_files: Option<ListFiles>,
+    // Lines are separated by NUL instead of newline characters
+    null_data: bool,
 }
 
 pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
+    if config.recursive {
+        let mut files = vec![];
+        collect_files(Path::new(&config.file_path), &mut files)?;
+
+        let workers = thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(4);
+
+        let config = Arc::new(config);
+
+        if config.list_files.is_some() {
+            for path in list_files(&config, files, workers) {
+                println!("{}", path.display());
+            }
+            return Ok(());
+        }
+
+        for (path, lines) in search_files(&config, files, workers) {
+            for line in lines {
+                let line = config.format_line(&line);
+                print!("{}:{}{}", path.display(), line, config.terminator());
+            }
+        }
+
+        return Ok(());
+    }
+
     let contents = fs::read_to_string(&config.file_path)?;
 
-    let lines = if config.case_sensitive {
-        search(&config.query, &contents)
-    } else {
-        search_case_insensitive(&config.query, &contents)
-    };
+    if let Some(list) = config.list_files {
+        if has_match(&config, &contents) == (list == ListFiles::WithMatches) {
+            println!("{}", config.file_path);
+        }
+        return Ok(());
+    }
+
+    let lines = search_with_config(&config, &contents);
 
     for line in lines {
-        println!("{}", line);
+        print!("{}{}", config.format_line(line), config.terminator());
+    }
+    Ok(())
+}
+
+/// Recursively collects the paths of all files within a directory
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if path.is_dir() {
+        for entry in fs::read_dir(path)? {
+            collect_files(&entry?.path(), files)?;
+        }
+    } else {
+        files.push(path.to_path_buf());
     }
+
     Ok(())
 }
 
+/// Searches files in parallel with a pool of worker threads fed through a bounded channel.
+/// Results are grouped by path and sorted, so they do not depend on thread timing.
+pub fn search_files(
+    config: &Arc<Config>,
+    files: Vec<PathBuf>,
+    workers: usize,
+) -> Vec<(PathBuf, Vec<String>)> {
+    process_files(config, files, workers, |config, contents| {
+        let lines: Vec<String> = search_with_config(config, contents)
+            .into_iter()
+            .map(String::from)
+            .collect();
+        Some(lines).filter(|lines| !lines.is_empty())
+    })
+}
+
+/// Returns the sorted paths of the files selected by the list mode of the configuration,
+/// where looking for matches within a file stops at the first one
+pub fn list_files(config: &Arc<Config>, files: Vec<PathBuf>, workers: usize) -> Vec<PathBuf> {
+    let wanted = config.list_files != Some(ListFiles::WithoutMatch);
+    process_files(config, files, workers, move |config, contents| {
+        Some(()).filter(|_| has_match(config, contents) == wanted)
+    })
+    .into_iter()
+    .map(|(path, _)| path)
+    .collect()
+}
+
+/// Reads files on a pool of worker threads, keeping what `process` returns for each of them
+fn process_files<T, F>(
+    config: &Arc<Config>,
+    files: Vec<PathBuf>,
+    workers: usize,
+    process: F,
+) -> Vec<(PathBuf, T)>
+where
+    T: Send + 'static,
+    F: Fn(&Config, &str) -> Option<T> + Send + Sync + 'static,
+{
+    let workers = workers.max(1);
+    let process = Arc::new(process);
+
+    let (sender, receiver) = mpsc::sync_channel::<PathBuf>(workers);
+    // Workers take turns receiving paths from the same channel
+    let receiver = Arc::new(Mutex::new(receiver));
+    let results = Arc::new(Mutex::new(vec![]));
+
+    let handles: Vec<_> = (0..workers)
+        .map(|_| {
+            let config = Arc::clone(config);
+            let receiver = Arc::clone(&receiver);
+            let results = Arc::clone(&results);
+            let process = Arc::clone(&process);
+
+            thread::spawn(move || loop {
+                // The lock is released as soon as a path is received
+                let path = match receiver.lock().unwrap().recv() {
+                    Ok(path) => path,
+                    // Channel closed, no more files to search
+                    Err(_) => break,
+                };
+
+                let contents = match fs::read_to_string(&path) {
+                    Ok(contents) => contents,
+                    Err(err) => {
+                        eprintln!("{}: {}", path.display(), err);
+                        continue;
+                    }
+                };
+
+                if let Some(result) = process(&config, &contents) {
+                    results.lock().unwrap().push((path, result));
+                }
+            })
+        })
+        .collect();
+
+    for file in files {
+        sender.send(file).expect("Failed to send file to workers");
+    }
+    // Closing the channel lets workers exit their loop
+    drop(sender);
+
+    for handle in handles {
+        handle.join().expect("Worker thread panicked");
+    }
+
+    let mut results = std::mem::take(&mut *results.lock().unwrap());
+    results.sort_by(|a, b| a.0.cmp(&b.0));
+    results
+}
+
 impl Config {
-    pub fn new(mut args: env::Args) -> Result<Config, &'static str> {
+    /// Parses the arguments of the program, where the first one is the program name.
+    /// Positional arguments are one or more queries followed by the file path, while flags are:
+    /// - `-m N`, `--max-count N`: stop after N matching lines
+    /// - `-w`, `--word-regexp`: match whole words only
+    /// - `-x`, `--line-regexp`: match whole lines only, taking precedence over `-w`
+    /// - `--color`: highlight matches in red
+    /// - `-r`, `--recursive`: the file path is a directory to search recursively
+    /// - `-l`, `--files-with-matches`: print only the paths of files with a match
+    /// - `-L`, `--files-without-match`: print only the paths of files without a match
+    /// - `-z`, `--null-data`: lines are separated by NUL characters, as in output
+    pub fn new(mut args: impl Iterator<Item = String>) -> Result<Config, ConfigError> {
         args.next();
 
-        let query = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Can not get query string"),
-        };
+        let mut builder = ConfigBuilder::new();
+        let mut word = false;
+        let mut line = false;
+        let mut positionals = vec![];
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-m" | "--max-count" => {
+                    let count = args.next().and_then(|count| count.parse().ok());
+                    match count {
+                        Some(count) => builder = builder.max_count(count),
+                        None => return Err(ConfigError::InvalidFlag(arg)),
+                    }
+                }
+                "-w" | "--word-regexp" => word = true,
+                "-x" | "--line-regexp" => line = true,
+                "--color" => builder = builder.color(true),
+                "-r" | "--recursive" => builder = builder.recursive(true),
+                "-l" | "--files-with-matches" => {
+                    builder = builder.list_files(ListFiles::WithMatches)
+                }
+                "-L" | "--files-without-match" => {
+                    builder = builder.list_files(ListFiles::WithoutMatch)
+                }
+                "-z" | "--null-data" => builder = builder.null_data(true),
+                _ => positionals.push(arg),
+            }
+        }
 
-        let file_path = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Can not get file name"),
+        // The file path comes last, after all the queries
+        if positionals.len() > 1 {
+            builder = builder.file_path(positionals.pop().unwrap());
+        }
+        for query in positionals {
+            builder = builder.query(query);
+        }
+
+        let mode = if line {
+            MatchMode::Line
+        } else if word {
+            MatchMode::Word
+        } else {
+            MatchMode::Substring
         };
 
-        let case_sensitive = env::var("CASE_INSENSITIVE").is_err();
+        builder
+            .case_sensitive(env::var("CASE_INSENSITIVE").is_err())
+            .mode(mode)
+            .build()
+    }
+
+    /// Returns what ends each printed line
+    fn terminator(&self) -> char {
+        if self.null_data {
+            '\0'
+        } else {
+            '\n'
+        }
+    }
+
+    /// Returns a matching line ready to be printed
+    fn format_line(&self, line: &str) -> String {
+        if self.color {
+            highlight_any(line, &self.queries, !self.case_sensitive, self.mode)
+        } else {
+            line.to_string()
+        }
+    }
+}
+
+/// Builds a configuration step by step, without parsing command line arguments
+#[derive(Debug, Clone)]
+pub struct ConfigBuilder {
+    queries: Vec<String>,
+    file_path: Option<String>,
+    case_sensitive: bool,
+    max_count: Option<usize>,
+    mode: MatchMode,
+    color: bool,
+    recursive: bool,
+    list_files: Option<ListFiles>,
+    null_data: bool,
+}
+
+impl Default for ConfigBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConfigBuilder {
+    /// Starts from a case sensitive substring search without any other option
+    pub fn new() -> Self {
+        Self {
+            queries: vec![],
+            file_path: None,
+            case_sensitive: true,
+            max_count: None,
+            mode: MatchMode::Substring,
+            color: false,
+            recursive: false,
+            list_files: None,
+            null_data: false,
+        }
+    }
+
+    /// Adds a query, a line matches if it matches any of them
+    pub fn query(mut self, query: impl Into<String>) -> Self {
+        self.queries.push(query.into());
+        self
+    }
+
+    pub fn file_path(mut self, file_path: impl Into<String>) -> Self {
+        self.file_path = Some(file_path.into());
+        self
+    }
+
+    pub fn case_sensitive(mut self, case_sensitive: bool) -> Self {
+        self.case_sensitive = case_sensitive;
+        self
+    }
+
+    pub fn max_count(mut self, max_count: usize) -> Self {
+        self.max_count = Some(max_count);
+        self
+    }
+
+    pub fn mode(mut self, mode: MatchMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    pub fn list_files(mut self, list_files: ListFiles) -> Self {
+        self.list_files = Some(list_files);
+        self
+    }
+
+    pub fn null_data(mut self, null_data: bool) -> Self {
+        self.null_data = null_data;
+        self
+    }
+
+    /// Returns the configuration, as long as there is at least a query and a file path
+    pub fn build(self) -> Result<Config, ConfigError> {
+        if self.queries.is_empty() {
+            return Err(ConfigError::MissingQuery);
+        }
+        let file_path = self.file_path.ok_or(ConfigError::MissingFilePath)?;
 
         Ok(Config {
-            query,
+            queries: self.queries,
             file_path,
-            case_sensitive,
+            case_sensitive: self.case_sensitive,
+            max_count: self.max_count,
+            mode: self.mode,
+            color: self.color,
+            recursive: self.recursive,
+            list_files: self.list_files,
+            null_data: self.null_data,
         })
     }
 }
 
-fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    contents
-        .lines()
-        .filter(|line| line.contains(query))
-        .collect()
+pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
+    search_iter(query, contents).collect()
+}
+
+pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
+    search_case_insensitive_iter(query, contents).collect()
 }
 
-fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
+/// Lazily yields the lines containing `query`, scanning only as far as the caller consumes
+pub fn search_iter<'a, 'q>(query: &'q str, contents: &'a str) -> impl Iterator<Item = &'a str> + 'q
+where
+    'a: 'q,
+{
+    lines_containing(contents.lines(), query)
+}
+
+/// Filters lines containing `query`, pulling them from `lines` only as needed
+fn lines_containing<'a, 'q, I>(lines: I, query: &'q str) -> impl Iterator<Item = &'a str> + 'q
+where
+    'a: 'q,
+    I: Iterator<Item = &'a str> + 'q,
+{
+    lines.filter(move |line| line.contains(query))
+}
+
+/// Splits contents at NUL characters, where a trailing one does not start a new line,
+/// like `grep -z`. Lines keep their exact content, including any newline characters within them.
+fn null_lines(contents: &str) -> impl Iterator<Item = &str> {
+    contents.split_terminator('\0')
+}
+
+/// Lazy counterpart of `search_case_insensitive`
+pub fn search_case_insensitive_iter<'a>(
+    query: &str,
+    contents: &'a str,
+) -> impl Iterator<Item = &'a str> {
+    lines_containing_case_insensitive(contents.lines(), query)
+}
+
+/// Case insensitive counterpart of `lines_containing`
+fn lines_containing_case_insensitive<'a, I>(lines: I, query: &str) -> impl Iterator<Item = &'a str>
+where
+    I: Iterator<Item = &'a str>,
+{
     let query = query.to_lowercase();
-    contents
-        .lines()
-        .filter(|line| line.to_lowercase().contains(&query))
+    lines.filter(move |line| line.to_lowercase().contains(&query))
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Returns whether the text of `line` between `start` and `end` is a whole word
+fn is_whole_word(line: &str, start: usize, end: usize) -> bool {
+    let before = line[..start].chars().next_back();
+    let after = line[end..].chars().next();
+    !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char)
+}
+
+/// Returns whether `query` appears in `line` according to the match mode
+fn line_matches(line: &str, query: &str, mode: MatchMode) -> bool {
+    match mode {
+        MatchMode::Substring => line.contains(query),
+        MatchMode::Word => line
+            .match_indices(query)
+            .any(|(start, _)| is_whole_word(line, start, start + query.len())),
+        MatchMode::Line => line.trim() == query,
+    }
+}
+
+/// Searches contents honouring all the options of a configuration.
+/// A line is returned once, however many of the queries it matches.
+/// It returns as soon as `max_count` matching lines are found, without scanning the rest.
+pub fn search_with_config<'a>(config: &Config, contents: &'a str) -> Vec<&'a str> {
+    matching_lines(config, contents)
+        .take(config.max_count.unwrap_or(usize::MAX))
         .collect()
 }
 
+/// Returns whether any line matches, without scanning past the first match
+pub fn has_match(config: &Config, contents: &str) -> bool {
+    matching_lines(config, contents).next().is_some()
+}
+
+/// Lazily yields the lines matching any of the queries of a configuration
+fn matching_lines<'a, 'c>(
+    config: &'c Config,
+    contents: &'a str,
+) -> impl Iterator<Item = &'a str> + 'c
+where
+    'a: 'c,
+{
+    let queries: Vec<String> = if config.case_sensitive {
+        config.queries.clone()
+    } else {
+        config.queries.iter().map(|q| q.to_lowercase()).collect()
+    };
+
+    let lines: Box<dyn Iterator<Item = &'a str>> = if config.null_data {
+        Box::new(null_lines(contents))
+    } else {
+        Box::new(contents.lines())
+    };
+
+    lines.filter(move |line| {
+        let line = if config.case_sensitive {
+            Cow::Borrowed(*line)
+        } else {
+            Cow::Owned(line.to_lowercase())
+        };
+        queries
+            .iter()
+            .any(|query| line_matches(&line, query, config.mode))
+    })
+}
+
+/// Returns the length in bytes of `query` if `haystack` starts with it
+fn match_len(haystack: &str, query: &str, case_insensitive: bool) -> Option<usize> {
+    let mut chars = haystack.char_indices();
+    let mut len = 0;
+
+    for q in query.chars() {
+        let (i, c) = chars.next()?;
+        let same = if case_insensitive {
+            c.to_lowercase().eq(q.to_lowercase())
+        } else {
+            c == q
+        };
+        if !same {
+            return None;
+        }
+        len = i + c.len_utf8();
+    }
+
+    Some(len)
+}
+
+/// Wraps every occurrence of `query` within `line` in ANSI red escape codes
+pub fn highlight(line: &str, query: &str, case_insensitive: bool) -> String {
+    highlight_any(line, &[query], case_insensitive, MatchMode::Substring)
+}
+
+/// Wraps every occurrence of any of the queries within `line` in ANSI red escape codes,
+/// preferring the longest query where several start at the same position.
+/// Only occurrences which count as a match for the mode are highlighted.
+pub fn highlight_any<Q: AsRef<str>>(
+    line: &str,
+    queries: &[Q],
+    case_insensitive: bool,
+    mode: MatchMode,
+) -> String {
+    const RED: &str = "\x1b[31m";
+    const RESET: &str = "\x1b[0m";
+
+    if mode == MatchMode::Line {
+        // The whole line matches, apart from surrounding whitespace, or nothing does
+        let trimmed = line.trim();
+        let matches = queries.iter().any(|query| {
+            match_len(trimmed, query.as_ref(), case_insensitive) == Some(trimmed.len())
+        });
+        if !matches || trimmed.is_empty() {
+            return line.to_string();
+        }
+
+        let start = line.len() - line.trim_start().len();
+        let end = start + trimmed.len();
+        return format!(
+            "{}{}{}{}{}",
+            &line[..start],
+            RED,
+            trimmed,
+            RESET,
+            &line[end..]
+        );
+    }
+
+    let mut highlighted = String::with_capacity(line.len());
+    let mut rest = line;
+
+    while let Some(c) = rest.chars().next() {
+        let start = line.len() - rest.len();
+        let longest = queries
+            .iter()
+            .filter(|query| !query.as_ref().is_empty())
+            .filter_map(|query| match_len(rest, query.as_ref(), case_insensitive))
+            .filter(|len| mode != MatchMode::Word || is_whole_word(line, start, start + len))
+            .max();
+
+        match longest {
+            Some(len) => {
+                // Keep the original text, as case may differ from the query
+                highlighted.push_str(RED);
+                highlighted.push_str(&rest[..len]);
+                highlighted.push_str(RESET);
+                rest = &rest[len..];
+            }
+            None => {
+                highlighted.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+
+    highlighted
+}
+
 // Some tests
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::cell::Cell;
 
     #[test]
     fn case_insensitive() {
@@ -93,4 +642,324 @@ Duct me.";
 
         assert_eq!(vec!["safe, fast, productive."], search(query, contents));
     }
+
+    #[test]
+    fn lazy_search() {
+        let contents = "hay\nneedle\nHAY\nneedle\nhay";
+        let visited = Cell::new(0);
+        let lines = || contents.lines().inspect(|_| visited.set(visited.get() + 1));
+
+        // Taking the first match stops right after the line it is on
+        let first: Vec<_> = lines_containing(lines(), "needle").take(1).collect();
+        assert_eq!(first, vec!["needle"]);
+        assert_eq!(visited.get(), 2);
+
+        visited.set(0);
+        let mut matches = lines_containing_case_insensitive(lines(), "hay");
+        assert_eq!(matches.next(), Some("hay"));
+        assert_eq!(visited.get(), 1);
+        assert_eq!(matches.next(), Some("HAY"));
+        assert_eq!(visited.get(), 3);
+
+        assert_eq!(
+            search_iter("needle", contents).collect::<Vec<_>>(),
+            search("needle", contents)
+        );
+    }
+
+    #[test]
+    fn max_count() {
+        let args = ["grep", "-m", "2", "match", "file.txt"];
+        let config = Config::new(args.iter().map(|arg| arg.to_string())).unwrap();
+
+        let contents = "match 1\nmatch 2\nno\nmatch 3\nmatch 4\nmatch 5";
+
+        assert_eq!(
+            vec!["match 1", "match 2"],
+            search_with_config(&config, contents)
+        );
+    }
+
+    #[test]
+    fn whole_word() {
+        let contents = "\
+Trust me.
+I like rust_lang.
+rust, safe and fast";
+
+        assert!(!line_matches("Trust me.", "rust", MatchMode::Word));
+        assert!(line_matches("Trust me.", "rust", MatchMode::Substring));
+
+        let args = ["grep", "-w", "rust", "file.txt"];
+        let config = Config::new(args.iter().map(|arg| arg.to_string())).unwrap();
+        assert_eq!(
+            vec!["rust, safe and fast"],
+            search_with_config(&config, contents)
+        );
+    }
+
+    #[test]
+    fn whole_line() {
+        let contents = "rust\n  rust  \ntrust\nrust me";
+
+        let args = ["grep", "-x", "rust", "file.txt"];
+        let config = Config::new(args.iter().map(|arg| arg.to_string())).unwrap();
+        assert_eq!(
+            vec!["rust", "  rust  "],
+            search_with_config(&config, contents)
+        );
+    }
+
+    #[test]
+    fn whole_word_case_insensitive() {
+        let mut config = Config::new(
+            ["grep", "-w", "RUST", "file.txt"]
+                .iter()
+                .map(|arg| arg.to_string()),
+        )
+        .unwrap();
+        config.case_sensitive = false;
+
+        assert_eq!(
+            vec!["Rust is fun"],
+            search_with_config(&config, "Rust is fun\nTrust me")
+        );
+    }
+
+    #[test]
+    fn highlight_matches() {
+        assert_eq!(
+            "a \x1b[31mrust\x1b[0m and t\x1b[31mrust\x1b[0m",
+            highlight("a rust and trust", "rust", false)
+        );
+
+        assert_eq!(
+            "\x1b[31mRust\x1b[0m and t\x1b[31mRUST\x1b[0m",
+            highlight("Rust and tRUST", "rust", true)
+        );
+
+        assert_eq!("Rust", highlight("Rust", "rust", false));
+    }
+
+    #[test]
+    fn any_of_queries() {
+        let args = ["grep", "safe", "fast", "file.txt"];
+        let mut config = Config::new(args.iter().map(|arg| arg.to_string())).unwrap();
+        assert_eq!(config.queries, vec!["safe", "fast"]);
+        assert_eq!(config.file_path, "file.txt");
+
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Fast and Safe.
+fast food";
+
+        // A line matching both queries is returned once
+        config.case_sensitive = true;
+        assert_eq!(
+            vec!["safe, fast, productive.", "fast food"],
+            search_with_config(&config, contents)
+        );
+
+        config.case_sensitive = false;
+        assert_eq!(
+            vec!["safe, fast, productive.", "Fast and Safe.", "fast food"],
+            search_with_config(&config, contents)
+        );
+
+        assert_eq!(
+            "\x1b[31mFast\x1b[0m and \x1b[31mSafe\x1b[0m.",
+            highlight_any(
+                "Fast and Safe.",
+                &config.queries,
+                true,
+                MatchMode::Substring
+            )
+        );
+    }
+
+    #[test]
+    fn highlight_with_mode() {
+        let args = ["grep", "-w", "--color", "rust", "file.txt"];
+        let config = Config::new(args.iter().map(|arg| arg.to_string())).unwrap();
+        assert_eq!(
+            "\x1b[31mrust\x1b[0m and trust",
+            config.format_line("rust and trust")
+        );
+        assert_eq!(
+            "I like rust_lang, \x1b[31mRust\x1b[0m.",
+            highlight_any("I like rust_lang, Rust.", &["rust"], true, MatchMode::Word)
+        );
+
+        // The whole line is highlighted, or nothing at all
+        assert_eq!(
+            "  \x1b[31mrust\x1b[0m ",
+            highlight_any("  rust ", &["rust"], false, MatchMode::Line)
+        );
+        assert_eq!(
+            "rust and trust",
+            highlight_any("rust and trust", &["rust"], false, MatchMode::Line)
+        );
+    }
+
+    #[test]
+    fn config_builder() {
+        let config = ConfigBuilder::new()
+            .query("rust")
+            .query("safe")
+            .file_path("poem.txt")
+            .case_sensitive(false)
+            .max_count(1)
+            .mode(MatchMode::Word)
+            .build()
+            .unwrap();
+
+        assert_eq!(config.queries, vec!["rust", "safe"]);
+        assert_eq!(config.file_path, "poem.txt");
+        assert_eq!(
+            vec!["Rust is safe"],
+            search_with_config(
+                &config,
+                "Rust is safe
+Trust me
+safe"
+            )
+        );
+    }
+
+    #[test]
+    fn config_builder_missing_fields() {
+        assert_eq!(
+            ConfigBuilder::new().file_path("poem.txt").build().err(),
+            Some(ConfigError::MissingQuery)
+        );
+        assert_eq!(
+            ConfigBuilder::new().query("rust").build().err(),
+            Some(ConfigError::MissingFilePath)
+        );
+        assert_eq!(
+            ConfigBuilder::new().build().err(),
+            Some(ConfigError::MissingQuery)
+        );
+    }
+
+    #[test]
+    fn config_errors() {
+        let new = |args: &[&str]| Config::new(args.iter().map(|arg| arg.to_string())).err();
+
+        assert_eq!(new(&["grep"]), Some(ConfigError::MissingQuery));
+        assert_eq!(new(&["grep", "-w"]), Some(ConfigError::MissingQuery));
+        assert_eq!(new(&["grep", "rust"]), Some(ConfigError::MissingFilePath));
+        assert_eq!(
+            new(&["grep", "rust", "file.txt", "-m"]),
+            Some(ConfigError::InvalidFlag(String::from("-m")))
+        );
+        assert_eq!(
+            new(&["grep", "--max-count", "many", "rust", "file.txt"]),
+            Some(ConfigError::InvalidFlag(String::from("--max-count")))
+        );
+        assert_eq!(new(&["grep", "rust", "file.txt"]), None);
+    }
+
+    #[test]
+    fn null_data() {
+        let contents = "Rust:\r\nsafe\0fast\0\nproductive, safe\nfast\0Pick three.\0";
+
+        let config = |query: &str| {
+            let args = ["grep", "-z", query, "file.txt"];
+            Config::new(args.iter().map(|arg| arg.to_string())).unwrap()
+        };
+
+        assert_eq!(
+            vec!["Rust:\r\nsafe", "\nproductive, safe\nfast"],
+            search_with_config(&config("safe"), contents)
+        );
+        assert_eq!(
+            vec!["Pick three."],
+            search_with_config(&config("three"), contents)
+        );
+        // A match can not span across lines
+        assert!(search_with_config(&config("safe\0fast"), contents).is_empty());
+
+        let mut config = config("FAST");
+        config.case_sensitive = false;
+        assert_eq!(
+            vec!["fast", "\nproductive, safe\nfast"],
+            search_with_config(&config, contents)
+        );
+        assert_eq!(config.terminator(), '\0');
+    }
+
+    #[test]
+    fn parallel_search() {
+        let dir = env::temp_dir().join(format!("grep-parallel-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("nested")).unwrap();
+
+        for i in 0..8 {
+            let contents = format!("needle {}\nhay\nneedle again {}", i, i);
+            fs::write(dir.join(format!("file{}.txt", i)), &contents).unwrap();
+        }
+        fs::write(dir.join("nested").join("deep.txt"), "deep needle").unwrap();
+        fs::write(dir.join("nested").join("empty.txt"), "nothing here").unwrap();
+
+        let args = ["grep", "-r", "needle", dir.to_str().unwrap()];
+        let config = Arc::new(Config::new(args.iter().map(|arg| arg.to_string())).unwrap());
+
+        let mut files = vec![];
+        collect_files(&dir, &mut files).unwrap();
+        assert_eq!(files.len(), 10);
+
+        let results = search_files(&config, files, 4);
+
+        let mut expected: Vec<(PathBuf, Vec<String>)> = (0..8)
+            .map(|i| {
+                (
+                    dir.join(format!("file{}.txt", i)),
+                    vec![format!("needle {}", i), format!("needle again {}", i)],
+                )
+            })
+            .collect();
+        expected.push((
+            dir.join("nested").join("deep.txt"),
+            vec![String::from("deep needle")],
+        ));
+        expected.sort_by(|a, b| a.0.cmp(&b.0));
+
+        assert_eq!(expected, results);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn list_files_by_match() {
+        let dir = env::temp_dir().join(format!("grep-list-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("nested")).unwrap();
+
+        fs::write(dir.join("one.txt"), "needle\nhay\nneedle").unwrap();
+        fs::write(dir.join("two.txt"), "hay\nhay").unwrap();
+        fs::write(dir.join("nested").join("three.txt"), "hay\nneedle").unwrap();
+        fs::write(dir.join("nested").join("four.txt"), "").unwrap();
+
+        let mut files = vec![];
+        collect_files(&dir, &mut files).unwrap();
+
+        let args = ["grep", "-r", "-l", "needle", dir.to_str().unwrap()];
+        let config = Arc::new(Config::new(args.iter().map(|arg| arg.to_string())).unwrap());
+        assert_eq!(
+            list_files(&config, files.clone(), 2),
+            vec![dir.join("nested").join("three.txt"), dir.join("one.txt")]
+        );
+
+        let args = ["grep", "-r", "-L", "needle", dir.to_str().unwrap()];
+        let config = Arc::new(Config::new(args.iter().map(|arg| arg.to_string())).unwrap());
+        assert_eq!(
+            list_files(&config, files, 2),
+            vec![dir.join("nested").join("four.txt"), dir.join("two.txt")]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -2,13 +2,19 @@ use std::env;
 use std::process;
 
 use command_line_program as clp;
+use command_line_program::ConfigError;
 
 fn main() {
     // Use idioms
     let config = clp::Config::new(env::args()).unwrap_or_else(|err| {
         // Print error to stderr
         eprintln!("Cannot parse arguments: {}", err);
-        process::exit(1);
+        // Missing arguments are probably due to not knowing how to use the program
+        if let ConfigError::MissingQuery | ConfigError::MissingFilePath = err {
+            eprintln!("Usage: command-line-program [OPTIONS] QUERY... FILE");
+        }
+        // Like grep, exit with 2 on wrong usage and keep 1 for failures while searching
+        process::exit(2);
     });
 
     if let Err(e) = clp::run(config) {
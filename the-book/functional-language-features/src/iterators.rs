@@ -9,6 +9,14 @@ pub fn run() {
     for val in v1_iter {
         println!("Got: {}", val);
     }
+
+    // Custom iterators work with fors as well
+    for val in Counter::new(0, 10, 2) {
+        println!("Even: {}", val);
+    }
+
+    let prices = [10.0, 11.0, 12.5, 12.0, 13.5];
+    println!("Moving average: {:?}", moving_average(&prices, 3));
 }
 
 // Iterators implement a trait named Iterator
@@ -76,6 +84,53 @@ impl Iterator for CounterTo5 {
     }
 }
 
+// Generalization of CounterTo5 with configurable bounds and step
+struct Counter {
+    current: u32,
+    end: u32,
+    step: u32,
+}
+
+impl Counter {
+    // Counts from start up to end excluded
+    fn new(start: u32, end: u32, step: u32) -> Counter {
+        assert!(step > 0, "A counter with step 0 would never end");
+        Counter {
+            current: start,
+            end,
+            step,
+        }
+    }
+}
+
+impl Iterator for Counter {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.current < self.end {
+            let value = self.current;
+            // Saturate so that a step past u32::MAX still stops the counter
+            self.current = self.current.saturating_add(self.step);
+            Some(value)
+        } else {
+            None
+        }
+    }
+}
+
+// Average of each sliding window of data, one value per window
+fn moving_average(data: &[f64], window: usize) -> Vec<f64> {
+    // Windows panics with a size of 0 and yields nothing
+    // when the size is larger than the data
+    if window == 0 {
+        return vec![];
+    }
+
+    data.windows(window)
+        .map(|values| values.iter().sum::<f64>() / window as f64)
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -171,4 +226,42 @@ mod tests {
 
         assert_eq!(sum, 18);
     }
+
+    #[test]
+    fn counter_with_step() {
+        let values: Vec<u32> = Counter::new(0, 10, 2).collect();
+        assert_eq!(values, vec![0, 2, 4, 6, 8]);
+
+        // The end is never reached when the step jumps over it
+        let values: Vec<u32> = Counter::new(1, 10, 4).collect();
+        assert_eq!(values, vec![1, 5, 9]);
+
+        assert_eq!(Counter::new(5, 5, 1).next(), None);
+        assert_eq!(Counter::new(u32::MAX - 1, u32::MAX, 8).count(), 1);
+    }
+
+    #[test]
+    fn methods_with_counter() {
+        let sum: u32 = Counter::new(0, 10, 2)
+            // 1,3,5,7,9
+            .map(|x| x + 1)
+            // 3,9
+            .filter(|x| x % 3 == 0)
+            // 12
+            .sum();
+
+        assert_eq!(sum, 12);
+    }
+
+    #[test]
+    fn moving_average_of_series() {
+        let data = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+
+        assert_eq!(moving_average(&data, 3), vec![2.0, 3.0, 4.0, 5.0]);
+        assert_eq!(moving_average(&data, 1), data.to_vec());
+        assert_eq!(moving_average(&data, 6), vec![3.5]);
+
+        assert!(moving_average(&data, 0).is_empty());
+        assert!(moving_average(&data, 7).is_empty());
+    }
 }
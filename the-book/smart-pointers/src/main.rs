@@ -98,35 +98,59 @@ pub trait Messenger {
     fn send(&self, msg: &str);
 }
 
-pub struct LimitTracker<'a, T: Messenger> {
-    messenger: &'a T,
+// Trait objects let the tracker notify messengers of different types, like observers
+pub struct LimitTracker<'a> {
+    messengers: Vec<&'a dyn Messenger>,
     value: usize,
     max: usize,
+    // Percentages of max paired with the message to send once they are reached
+    thresholds: Vec<(f64, String)>,
 }
 
-impl<'a, T> LimitTracker<'a, T>
-where
-    T: Messenger,
-{
-    pub fn new(messenger: &T, max: usize) -> LimitTracker<T> {
+impl<'a> LimitTracker<'a> {
+    pub fn new(messenger: &'a dyn Messenger, max: usize) -> LimitTracker<'a> {
+        let thresholds = vec![
+            (0.75, String::from("Warning: over 75% of quota")),
+            (0.9, String::from("Urgent: over 90% of quota")),
+            (1.0, String::from("Error: over quota")),
+        ];
+        LimitTracker::with_thresholds(messenger, max, thresholds)
+    }
+
+    pub fn with_thresholds(
+        messenger: &'a dyn Messenger,
+        max: usize,
+        thresholds: Vec<(f64, String)>,
+    ) -> LimitTracker<'a> {
         LimitTracker {
-            messenger,
+            messengers: vec![messenger],
             value: 0,
             max,
+            thresholds,
         }
     }
 
+    // Another messenger which gets notified along with the others
+    pub fn add_messenger(&mut self, messenger: &'a dyn Messenger) {
+        self.messengers.push(messenger);
+    }
+
     pub fn set_value(&mut self, value: usize) {
         self.value = value;
 
         let percentage_of_max = self.value as f64 / self.max as f64;
 
-        if percentage_of_max >= 1.0 {
-            self.messenger.send("Error: over quota");
-        } else if percentage_of_max >= 0.9 {
-            self.messenger.send("Urgent: over 90% of quota");
-        } else if percentage_of_max >= 0.75 {
-            self.messenger.send("Warning: over 75% of quota");
+        // Only the message of the highest threshold reached is sent
+        let reached = self
+            .thresholds
+            .iter()
+            .filter(|(threshold, _)| percentage_of_max >= *threshold)
+            .max_by(|a, b| a.0.total_cmp(&b.0));
+
+        if let Some((_, message)) = reached {
+            for messenger in &self.messengers {
+                messenger.send(message);
+            }
         }
     }
 }
@@ -172,6 +196,54 @@ mod tests {
         // Borrow a non mutable reference of the vector
         assert_eq!(mock_messenger.sent_messages.borrow().len(), 1);
     }
+
+    #[test]
+    fn notify_all_messengers() {
+        let first = MockMessenger::new();
+        let second = MockMessenger::new();
+        let mut limit_tracker = LimitTracker::new(&first, 100);
+        limit_tracker.add_messenger(&second);
+
+        limit_tracker.set_value(95);
+
+        let expected = vec!["Urgent: over 90% of quota"];
+        assert_eq!(*first.sent_messages.borrow(), expected);
+        assert_eq!(*second.sent_messages.borrow(), expected);
+    }
+
+    #[test]
+    fn custom_thresholds() {
+        let mock_messenger = MockMessenger::new();
+        let thresholds = vec![
+            (0.5, String::from("Half way")),
+            (0.25, String::from("A quarter")),
+        ];
+        let mut limit_tracker = LimitTracker::with_thresholds(&mock_messenger, 100, thresholds);
+
+        limit_tracker.set_value(10);
+        limit_tracker.set_value(30);
+        limit_tracker.set_value(60);
+
+        assert_eq!(
+            *mock_messenger.sent_messages.borrow(),
+            vec!["A quarter", "Half way"]
+        );
+    }
+
+    #[test]
+    fn nan_threshold() {
+        let mock_messenger = MockMessenger::new();
+        let thresholds = vec![
+            (f64::NAN, String::from("Never")),
+            (0.5, String::from("Half way")),
+        ];
+        let mut limit_tracker = LimitTracker::with_thresholds(&mock_messenger, 100, thresholds);
+
+        // A NaN threshold is never reached, and does not get in the way of the others
+        limit_tracker.set_value(60);
+
+        assert_eq!(*mock_messenger.sent_messages.borrow(), vec!["Half way"]);
+    }
 }
 
 #[derive(Debug)]
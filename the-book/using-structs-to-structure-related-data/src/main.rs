@@ -148,8 +148,85 @@ fn method_syntax() {
     println!("We have made a square: {:?}", square);
 }
 
+// Different structs can share behavior through a trait
+trait Shape {
+    fn area(&self) -> f64;
+}
+
+impl Shape for Rect {
+    fn area(&self) -> f64 {
+        // Calls the method of Rect, as inherent methods come first
+        self.area() as f64
+    }
+}
+
+struct Circle {
+    radius: f64,
+}
+
+impl Shape for Circle {
+    fn area(&self) -> f64 {
+        std::f64::consts::PI * self.radius * self.radius
+    }
+}
+
+struct Triangle {
+    base: f64,
+    height: f64,
+}
+
+impl Shape for Triangle {
+    fn area(&self) -> f64 {
+        self.base * self.height / 2.0
+    }
+}
+
+// Each box can hold a different shape, and the right area method
+// is looked up at runtime, which is called dynamic dispatch
+fn total_area(shapes: &[Box<dyn Shape>]) -> f64 {
+    shapes.iter().map(|shape| shape.area()).sum()
+}
+
+fn shapes() {
+    let shapes: Vec<Box<dyn Shape>> = vec![
+        Box::new(Rect::square(2)),
+        Box::new(Circle { radius: 1.0 }),
+        Box::new(Triangle {
+            base: 3.0,
+            height: 4.0,
+        }),
+    ];
+
+    println!("Total area of the shapes is {}", total_area(&shapes));
+}
+
 fn main() {
     define_and_instantiate_structs();
     rectangle_area_calculator();
     method_syntax();
+    shapes();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn total_area_of_mixed_shapes() {
+        let shapes: Vec<Box<dyn Shape>> = vec![
+            Box::new(Rect {
+                width: 2,
+                height: 3,
+            }),
+            Box::new(Circle { radius: 2.0 }),
+            Box::new(Triangle {
+                base: 4.0,
+                height: 5.0,
+            }),
+        ];
+
+        let expected = 6.0 + std::f64::consts::PI * 4.0 + 10.0;
+        assert!((total_area(&shapes) - expected).abs() < 1e-9);
+        assert_eq!(total_area(&[]), 0.0);
+    }
 }
@@ -1,5 +1,7 @@
+mod sparse;
 mod utils;
 
+pub use sparse::SparseUniverse;
 use wasm_bindgen::prelude::*;
 
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
@@ -35,6 +37,24 @@ impl Cell {
     }
 }
 
+/// Symbols used by default when rendering the universe as text
+const ALIVE_SYMBOL: char = '⬛';
+const DEAD_SYMBOL: char = '⬜';
+
+/// Small deterministic pseudo-random generator (SplitMix64),
+/// so that the same seed always produces the same sequence on every platform
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
 // This annotation helps us define and work with opaque
 // handles to JavaScript objects or Boxed Rust structures
 #[wasm_bindgen]
@@ -42,6 +62,10 @@ pub struct Universe {
     width: u32,
     height: u32,
     cells: Vec<Cell>,
+    /// Neighbor lookups performed by the last tick
+    last_tick_ops: u64,
+    alive_symbol: char,
+    dead_symbol: char,
 }
 
 impl Universe {
@@ -72,6 +96,14 @@ impl Universe {
         count
     }
 
+    /// Returns how many cells are alive
+    pub fn live_count(&self) -> u64 {
+        self.cells
+            .iter()
+            .filter(|&&cell| cell == Cell::Alive)
+            .count() as u64
+    }
+
     /// Returns the dead and alive values of the universe
     pub fn get_cells(&self) -> &[Cell] {
         &self.cells
@@ -93,12 +125,15 @@ impl Universe {
     /// Computes the next generation from the current one
     pub fn tick(&mut self) {
         let mut next = self.cells.clone();
+        let mut ops = 0;
 
         for row in 0..self.height {
             for col in 0..self.width {
                 let idx = self.get_index(row, col);
                 let cell = self.cells[idx];
                 let live_neighbors = self.count_live_neighbors(row, col);
+                // Every cell looks up each of its 8 neighbors
+                ops += 8;
 
                 let next_cell = match (cell, live_neighbors) {
                     // Rule 1: underpopulation
@@ -126,6 +161,13 @@ impl Universe {
         }
 
         self.cells = next;
+        self.last_tick_ops = ops;
+    }
+
+    /// Returns how many neighbor lookups the last tick performed,
+    /// useful to measure the work saved by an optimized implementation
+    pub fn last_tick_ops(&self) -> u64 {
+        self.last_tick_ops
     }
 
     /// Initializes an universe with an interesting pattern of live cells
@@ -151,9 +193,50 @@ impl Universe {
             width: width as u32,
             height: height as u32,
             cells,
+            last_tick_ops: 0,
+            alive_symbol: ALIVE_SYMBOL,
+            dead_symbol: DEAD_SYMBOL,
         }
     }
 
+    /// Runs a reproducible workload for comparing tick implementations:
+    /// it seeds a random soup where about half of the cells are alive,
+    /// ticks it for a number of generations, and returns the final live count
+    pub fn bench_random(width: u32, height: u32, seed: u64, generations: u32) -> u64 {
+        let mut rng = SplitMix64(seed);
+        let cells = (0..width * height)
+            .map(|_| {
+                if rng.next() & 1 == 1 {
+                    Cell::Alive
+                } else {
+                    Cell::Dead
+                }
+            })
+            .collect();
+
+        let mut universe = Self {
+            width,
+            height,
+            cells,
+            last_tick_ops: 0,
+            alive_symbol: ALIVE_SYMBOL,
+            dead_symbol: DEAD_SYMBOL,
+        };
+
+        for _ in 0..generations {
+            universe.tick();
+        }
+
+        universe.live_count()
+    }
+
+    /// Sets the symbols used by `render`, such as `#` and `.` for terminals
+    /// where the default emoji do not display well
+    pub fn set_symbols(&mut self, alive: char, dead: char) {
+        self.alive_symbol = alive;
+        self.dead_symbol = dead;
+    }
+
     pub fn render(&self) -> String {
         // Automatically provided by the Display trait
         self.to_string()
@@ -184,6 +267,32 @@ impl Universe {
         self.cells = (0..self.width * height).map(|_i| Cell::Dead).collect();
     }
 
+    /// Returns the number of live neighbors of a cell, wrapping around the edges,
+    /// or 0 when the position is outside of the universe
+    pub fn neighbors(&self, row: u32, col: u32) -> u8 {
+        if row >= self.height || col >= self.width {
+            return 0;
+        }
+        self.count_live_neighbors(row, col)
+    }
+
+    /// Kills every cell, keeping the size of the universe and its render symbols
+    pub fn clear(&mut self) {
+        for cell in self.cells.iter_mut() {
+            *cell = Cell::Dead;
+        }
+        self.last_tick_ops = 0;
+    }
+
+    /// Returns whether the cell at a given position is alive,
+    /// or false when the position is outside of the universe
+    pub fn is_alive(&self, row: u32, col: u32) -> bool {
+        if row >= self.height || col >= self.width {
+            return false;
+        }
+        self.cells[self.get_index(row, col)] == Cell::Alive
+    }
+
     /// Flips the state of a cell at a given position
     pub fn toggle_cell(&mut self, row: u32, col: u32) {
         let idx = self.get_index(row, col);
@@ -195,7 +304,11 @@ impl std::fmt::Display for Universe {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for line in self.cells.as_slice().chunks(self.width as usize) {
             for &cell in line {
-                let symbol = if cell == Cell::Dead { '⬜' } else { '⬛' };
+                let symbol = if cell == Cell::Dead {
+                    self.dead_symbol
+                } else {
+                    self.alive_symbol
+                };
                 write!(f, "{}", symbol)?;
             }
             write!(f, "\n")?;
@@ -0,0 +1,110 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{Cell, Universe};
+
+/// Universe storing only the coordinates of live cells, which suits
+/// large universes where most of the cells are dead
+pub struct SparseUniverse {
+    width: u32,
+    height: u32,
+    /// Row and column of each live cell
+    live: HashSet<(u32, u32)>,
+}
+
+impl SparseUniverse {
+    /// Creates a universe where every cell is dead
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            live: HashSet::new(),
+        }
+    }
+
+    /// Set cells to be alive by passing the row and column of each cell,
+    /// ignoring those outside of the universe
+    pub fn set_cells(&mut self, cells: &[(u32, u32)]) {
+        let (width, height) = (self.width, self.height);
+        self.live.extend(
+            cells
+                .iter()
+                .cloned()
+                .filter(|&(row, col)| row < height && col < width),
+        );
+    }
+
+    pub fn is_alive(&self, row: u32, col: u32) -> bool {
+        self.live.contains(&(row, col))
+    }
+
+    /// Computes the next generation by looking only at live cells and their neighbors
+    pub fn tick(&mut self) {
+        // An empty universe has no cells, nor neighbors to wrap around to
+        if self.width == 0 || self.height == 0 {
+            return;
+        }
+
+        // Each live cell adds one to the count of each of its neighbors,
+        // so dead cells far from any live cell are never visited
+        let mut neighbor_counts: HashMap<(u32, u32), u8> = HashMap::new();
+
+        for &(row, col) in &self.live {
+            // Same deltas and modulo as the dense universe to wrap around the edges
+            for delta_row in [self.height - 1, 0, 1].iter().cloned() {
+                for delta_col in [self.width - 1, 0, 1].iter().cloned() {
+                    if delta_row == 0 && delta_col == 0 {
+                        continue;
+                    }
+
+                    let neighbor_row = (row + delta_row) % self.height;
+                    let neighbor_col = (col + delta_col) % self.width;
+                    *neighbor_counts
+                        .entry((neighbor_row, neighbor_col))
+                        .or_insert(0) += 1;
+                }
+            }
+        }
+
+        self.live = neighbor_counts
+            .into_iter()
+            .filter(|&(pos, count)| count == 3 || (count == 2 && self.live.contains(&pos)))
+            .map(|(pos, _)| pos)
+            .collect();
+    }
+}
+
+impl From<&Universe> for SparseUniverse {
+    fn from(universe: &Universe) -> Self {
+        let mut sparse = SparseUniverse::new(universe.width, universe.height);
+
+        for row in 0..universe.height {
+            for col in 0..universe.width {
+                if universe.cells[universe.get_index(row, col)] == Cell::Alive {
+                    sparse.live.insert((row, col));
+                }
+            }
+        }
+
+        sparse
+    }
+}
+
+impl From<&SparseUniverse> for Universe {
+    fn from(sparse: &SparseUniverse) -> Self {
+        let mut universe = Universe {
+            width: sparse.width,
+            height: sparse.height,
+            cells: vec![Cell::Dead; (sparse.width * sparse.height) as usize],
+            last_tick_ops: 0,
+            alive_symbol: crate::ALIVE_SYMBOL,
+            dead_symbol: crate::DEAD_SYMBOL,
+        };
+
+        for &(row, col) in &sparse.live {
+            let idx = universe.get_index(row, col);
+            universe.cells[idx] = Cell::Alive;
+        }
+
+        universe
+    }
+}
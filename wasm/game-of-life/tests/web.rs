@@ -6,7 +6,7 @@ extern crate wasm_bindgen_test;
 use wasm_bindgen_test::*;
 
 extern crate game_of_life;
-use game_of_life::Universe;
+use game_of_life::{Cell, SparseUniverse, Universe};
 
 wasm_bindgen_test_configure!(run_in_browser);
 
@@ -41,3 +41,132 @@ pub fn test_tick() {
     input_universe.tick();
     assert_eq!(&input_universe.get_cells(), &expected_universe.get_cells());
 }
+
+#[wasm_bindgen_test]
+pub fn test_tick_ops() {
+    let mut universe = input_spaceship();
+    assert_eq!(universe.last_tick_ops(), 0);
+
+    // The naive implementation counts all the neighbors of every cell
+    universe.tick();
+    assert_eq!(universe.last_tick_ops(), 6 * 6 * 8);
+}
+
+#[wasm_bindgen_test]
+pub fn test_sparse_tick() {
+    let mut dense = input_spaceship();
+    let mut sparse = SparseUniverse::from(&dense);
+
+    for _ in 0..10 {
+        dense.tick();
+        sparse.tick();
+        assert_eq!(dense.get_cells(), Universe::from(&sparse).get_cells());
+    }
+
+    // The glider wraps around the edges and is still alive
+    assert_eq!(
+        dense
+            .get_cells()
+            .iter()
+            .filter(|&&cell| cell == Cell::Alive)
+            .count(),
+        5
+    );
+}
+
+#[wasm_bindgen_test]
+pub fn test_sparse_out_of_range() {
+    let mut sparse = SparseUniverse::new(4, 4);
+    sparse.set_cells(&[(1, 1), (4, 0), (0, 4), (u32::MAX, u32::MAX)]);
+
+    assert!(sparse.is_alive(1, 1));
+    assert!(!sparse.is_alive(4, 0));
+    assert!(!sparse.is_alive(0, 4));
+
+    // Only the cell in range ends up in the dense universe
+    let dense = Universe::from(&sparse);
+    assert_eq!(dense.live_count(), 1);
+    assert!(dense.is_alive(1, 1));
+}
+
+#[wasm_bindgen_test]
+pub fn test_sparse_empty() {
+    for &(width, height) in [(0, 0), (0, 4), (4, 0)].iter() {
+        let mut sparse = SparseUniverse::new(width, height);
+        sparse.set_cells(&[(0, 0)]);
+        sparse.tick();
+        assert!(!sparse.is_alive(0, 0));
+        assert!(Universe::from(&sparse).get_cells().is_empty());
+    }
+}
+
+#[wasm_bindgen_test]
+pub fn test_neighbors() {
+    let mut universe = Universe::new();
+    universe.set_width(4);
+    universe.set_height(4);
+    // Cells around the top-left corner, some of them across the edges
+    universe.set_cells(&[(0, 1), (1, 1), (3, 3), (3, 0), (2, 2)]);
+
+    assert_eq!(universe.neighbors(0, 0), 4);
+    assert_eq!(universe.neighbors(3, 3), 2);
+    assert_eq!(universe.neighbors(1, 2), 3);
+
+    // Outside of the universe
+    assert_eq!(universe.neighbors(4, 0), 0);
+    assert_eq!(universe.neighbors(0, 4), 0);
+}
+
+#[wasm_bindgen_test]
+pub fn test_bench_random() {
+    let count = Universe::bench_random(32, 24, 42, 50);
+    assert_eq!(count, Universe::bench_random(32, 24, 42, 50));
+
+    // A different seed starts from a different soup
+    assert_ne!(
+        Universe::bench_random(32, 24, 42, 0),
+        Universe::bench_random(32, 24, 7, 0)
+    );
+    assert!(Universe::bench_random(32, 24, 42, 0) > 0);
+}
+
+#[wasm_bindgen_test]
+pub fn test_render_symbols() {
+    let mut universe = Universe::new();
+    universe.set_width(3);
+    universe.set_height(2);
+    universe.set_cells(&[(0, 1), (1, 0)]);
+    assert_eq!(universe.render(), "⬜⬛⬜\n⬛⬜⬜\n");
+
+    universe.set_symbols('#', '.');
+    assert_eq!(universe.render(), ".#.\n#..\n");
+}
+
+#[wasm_bindgen_test]
+pub fn test_is_alive() {
+    let mut universe = Universe::new();
+    universe.set_width(4);
+    universe.set_height(4);
+    universe.set_cells(&[(1, 2)]);
+
+    assert!(universe.is_alive(1, 2));
+    assert!(!universe.is_alive(1, 3));
+
+    // Outside of the universe
+    assert!(!universe.is_alive(4, 2));
+    assert!(!universe.is_alive(1, 4));
+}
+
+#[wasm_bindgen_test]
+pub fn test_clear() {
+    let mut universe = input_spaceship();
+    universe.tick();
+    universe.set_cells(&[(0, 0), (5, 5)]);
+    assert!(universe.live_count() > 0);
+
+    universe.clear();
+    assert_eq!(universe.live_count(), 0);
+    assert_eq!(universe.last_tick_ops(), 0);
+    assert_eq!(universe.get_width(), 6);
+    assert_eq!(universe.get_height(), 6);
+}
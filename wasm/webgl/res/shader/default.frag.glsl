@@ -5,21 +5,39 @@ varying vec4 color;
 varying vec3 normal;
 varying vec2 uv;
 
+struct Material {
+    float ambient;
+    float diffuse;
+    float specular;
+};
+
 uniform vec4 select_color;
 uniform sampler2D tex_sampler;
 uniform vec3 light_color;
 uniform vec3 light_position;
+uniform bool gamma_correct;
+uniform float shininess;
+uniform Material material;
 
 void main() {
-    vec3 light_direction = light_position - position;
-    float n_dot_l = max(
-        dot(
-            normalize(light_direction),
-            normalize(normal)
-        ),
-        0.0
-    );
-    vec3 diffuse = light_color * vec3(color) * n_dot_l;
-    vec3 ambient = light_color * vec3(color) * 0.1;
-    gl_FragColor = select_color + vec4(diffuse + ambient, color.a) * texture2D(tex_sampler, uv);
+    vec3 light_direction = normalize(light_position - position);
+    vec3 n = normalize(normal);
+    float n_dot_l = max(dot(light_direction, n), 0.0);
+    vec3 diffuse = light_color * vec3(color) * n_dot_l * material.diffuse;
+    vec3 ambient = light_color * vec3(color) * material.ambient;
+
+    // Blinn-Phong specular, the camera sits at the origin of view space
+    vec3 view_direction = normalize(-position);
+    vec3 halfway = normalize(light_direction + view_direction);
+    float n_dot_h = max(dot(n, halfway), 0.0);
+    float specular_factor = n_dot_l > 0.0 ? pow(n_dot_h, shininess) : 0.0;
+    vec3 specular = light_color * specular_factor * material.specular;
+
+    vec4 frag_color = select_color + vec4(diffuse + ambient, color.a) * texture2D(tex_sampler, uv);
+    frag_color.rgb += specular;
+    if (gamma_correct) {
+        // Lighting is computed in linear space, while displays expect sRGB
+        frag_color.rgb = pow(frag_color.rgb, vec3(1.0 / 2.2));
+    }
+    gl_FragColor = frag_color;
 }
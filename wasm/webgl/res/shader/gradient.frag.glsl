@@ -0,0 +1,10 @@
+precision mediump float;
+
+uniform vec3 top_color;
+uniform vec3 bottom_color;
+
+varying float height;
+
+void main() {
+    gl_FragColor = vec4(mix(bottom_color, top_color, height), 1.0);
+}
@@ -0,0 +1,9 @@
+attribute vec3 in_position;
+
+varying float height;
+
+void main() {
+    // Map the unit quad onto the whole screen
+    gl_Position = vec4(in_position.xy * 2.0 - 1.0, 0.0, 1.0);
+    height = in_position.y;
+}
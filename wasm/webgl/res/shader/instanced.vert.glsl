@@ -0,0 +1,24 @@
+attribute vec3 in_position;
+attribute vec4 in_color;
+attribute vec3 in_normal;
+attribute vec2 in_uv;
+// Per-instance model transform
+attribute mat4 in_transform;
+
+varying vec3 position;
+varying vec4 color;
+varying vec3 normal;
+varying vec2 uv;
+
+uniform mat4 view;
+uniform mat4 proj;
+
+void main() {
+    uv = in_uv;
+    vec4 pos4 = view * in_transform * vec4(in_position, 1.0);
+    position = pos4.xyz;
+    gl_Position = proj * pos4;
+    // Node transforms are rigid, so their rotation transforms normals as well
+    normal = mat3(in_transform) * normalize(in_normal);
+    color = in_color;
+}
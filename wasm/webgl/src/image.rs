@@ -0,0 +1,108 @@
+/// CPU-side RGBA image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    /// Four bytes per pixel, row by row
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    const CHANNELS: usize = 4;
+
+    /// Creates an image from raw RGBA pixels
+    pub fn from_raw(width: u32, height: u32, pixels: Vec<u8>) -> Self {
+        assert_eq!(
+            pixels.len(),
+            width as usize * height as usize * Self::CHANNELS,
+            "Pixels do not match a {}x{} RGBA image",
+            width,
+            height
+        );
+
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Returns the RGBA values of the pixel at column x and row y
+    pub fn pixel(&self, x: u32, y: u32) -> &[u8] {
+        let start = (y as usize * self.width as usize + x as usize) * Self::CHANNELS;
+        &self.pixels[start..start + Self::CHANNELS]
+    }
+
+    /// Returns a new image scaled to the new size by picking the nearest pixel
+    pub fn resize_nearest(&self, new_width: u32, new_height: u32) -> Image {
+        let mut pixels =
+            Vec::with_capacity(new_width as usize * new_height as usize * Self::CHANNELS);
+
+        for y in 0..new_height {
+            let src_y = (y as u64 * self.height as u64 / new_height as u64) as u32;
+            for x in 0..new_width {
+                let src_x = (x as u64 * self.width as u64 / new_width as u64) as u32;
+                pixels.extend_from_slice(self.pixel(src_x, src_y));
+            }
+        }
+
+        Image::from_raw(new_width, new_height, pixels)
+    }
+
+    /// Returns a new image with the region starting at x and y of the given size
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Image {
+        assert!(
+            x + width <= self.width && y + height <= self.height,
+            "Crop region is out of the image bounds"
+        );
+
+        let mut pixels = Vec::with_capacity(width as usize * height as usize * Self::CHANNELS);
+
+        for row in y..y + height {
+            let start = (row as usize * self.width as usize + x as usize) * Self::CHANNELS;
+            let end = start + width as usize * Self::CHANNELS;
+            pixels.extend_from_slice(&self.pixels[start..end]);
+        }
+
+        Image::from_raw(width, height, pixels)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn red_green_blue_white() -> Image {
+        let pixels = vec![
+            255u8, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255, 255, 255, 255, 255,
+        ];
+        Image::from_raw(2, 2, pixels)
+    }
+
+    #[test]
+    fn resize_nearest() {
+        let image = red_green_blue_white();
+        let resized = image.resize_nearest(4, 4);
+
+        assert_eq!(resized.width, 4);
+        assert_eq!(resized.height, 4);
+
+        // Every source pixel becomes a 2x2 block
+        for y in 0..4 {
+            for x in 0..4 {
+                assert_eq!(resized.pixel(x, y), image.pixel(x / 2, y / 2));
+            }
+        }
+    }
+
+    #[test]
+    fn crop() {
+        let image = red_green_blue_white();
+        let cropped = image.crop(1, 0, 1, 2);
+
+        assert_eq!(cropped.width, 1);
+        assert_eq!(cropped.height, 2);
+        assert_eq!(cropped.pixel(0, 0), image.pixel(1, 0));
+        assert_eq!(cropped.pixel(0, 1), image.pixel(1, 1));
+    }
+}
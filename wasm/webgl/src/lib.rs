@@ -1,11 +1,21 @@
+mod image;
+mod scene;
 mod utils;
 
-use std::{cell::RefCell, rc::Rc};
+pub use image::Image;
+
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
 
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 
-use nalgebra::{Isometry3, Point3, Translation3, UnitQuaternion, Vector3};
+#[macro_use]
+extern crate serde_derive;
+
+use nalgebra::{Isometry3, Matrix4, Point3, Translation3, UnitQuaternion, Vector3};
 use web_sys::WebGlRenderingContext as GL;
 use web_sys::*;
 
@@ -44,28 +54,79 @@ impl ToJsArray for Vec<Vertex> {
     }
 }
 
-/// Returns a WebGL Context
-fn get_canvas() -> Result<HtmlCanvasElement, JsValue> {
+/// Creates a JS error with a message which shows up in the browser console
+fn js_error(message: &str) -> JsValue {
+    js_sys::Error::new(message).into()
+}
+
+/// Turns a failure into a JS error with a descriptive message instead of panicking
+trait OrJsError<T> {
+    fn or_js_error(self, message: &str) -> Result<T, JsValue>;
+}
+
+impl<T> OrJsError<T> for Option<T> {
+    fn or_js_error(self, message: &str) -> Result<T, JsValue> {
+        self.ok_or_else(|| js_error(message))
+    }
+}
+
+impl<T, E> OrJsError<T> for Result<T, E> {
+    fn or_js_error(self, message: &str) -> Result<T, JsValue> {
+        self.map_err(|_| js_error(message))
+    }
+}
+
+/// Canvas used when no element id is given
+const DEFAULT_CANVAS_ID: &str = "area";
+
+/// Returns the canvas element with the given id, resized to its client area
+fn get_canvas(id: &str) -> Result<HtmlCanvasElement, JsValue> {
     utils::set_panic_hook();
 
-    let doc = window().unwrap().document().unwrap();
-    let canvas = doc.get_element_by_id("area").unwrap();
-    let canvas: HtmlCanvasElement = canvas.dyn_into::<HtmlCanvasElement>()?;
+    let doc = window()
+        .or_js_error("Global window not available")?
+        .document()
+        .or_js_error("Window has no document")?;
+    let canvas = doc
+        .get_element_by_id(id)
+        .or_js_error(&format!("canvas #{} not found", id))?;
+    let canvas: HtmlCanvasElement = canvas
+        .dyn_into::<HtmlCanvasElement>()
+        .or_js_error(&format!("Element #{} is not a canvas", id))?;
     canvas.set_width(canvas.client_width() as u32);
     canvas.set_height(canvas.client_height() as u32);
 
     Ok(canvas)
 }
 
-fn get_gl_context(canvas: &HtmlCanvasElement) -> Result<GL, JsValue> {
-    Ok(canvas.get_context("webgl")?.unwrap().dyn_into::<GL>()?)
+/// Returns a WebGL2 context if supported, otherwise a WebGL1 one,
+/// along with whether WebGL2 was obtained
+fn get_gl_context(canvas: &HtmlCanvasElement, antialias: bool) -> Result<(GL, bool), JsValue> {
+    let attributes = WebGlContextAttributes::new();
+    attributes.set_antialias(antialias);
+    attributes.set_depth(true);
+    attributes.set_premultiplied_alpha(false);
+
+    // WebGL2 is a superset of WebGL1 and web-sys methods are looked up
+    // on the JS object itself, so the WebGL1 bindings can drive both
+    if let Some(context) = canvas.get_context_with_context_options("webgl2", &attributes)? {
+        return Ok((context.unchecked_into::<GL>(), true));
+    }
+
+    let context = canvas
+        .get_context_with_context_options("webgl", &attributes)?
+        .or_js_error("WebGL not supported")?
+        .dyn_into::<GL>()
+        .or_js_error("Context is not a WebGL rendering context")?;
+
+    Ok((context, false))
 }
 
 /// Short WebGL program which simply clears a drawing area specified by a canvas tag
 #[wasm_bindgen]
 pub fn clear_drawing_area() -> Result<(), JsValue> {
-    let canvas = get_canvas().unwrap();
-    let gl = get_gl_context(&canvas)?;
+    let canvas = get_canvas(DEFAULT_CANVAS_ID)?;
+    let (gl, _) = get_gl_context(&canvas, true)?;
 
     gl.clear_color(0.0, 0.0, 0.0, 1.0);
     gl.clear(GL::COLOR_BUFFER_BIT);
@@ -157,6 +218,8 @@ struct PointPipeline {
     position_loc: i32,
     point_size_loc: i32,
     color_loc: Option<WebGlUniformLocation>,
+    /// Positions of points drawn in batch
+    position_buffer: Option<WebGlBuffer>,
 }
 
 impl PointPipeline {
@@ -167,16 +230,33 @@ impl PointPipeline {
         let position_loc = program.get_attrib_loc("position");
         let point_size_loc = program.get_attrib_loc("point_size");
         let color_loc = program.get_uniform_loc("color");
+        let position_buffer = gl.create_buffer();
 
         Self {
             program,
             position_loc,
             point_size_loc,
             color_loc,
+            position_buffer,
         }
     }
 }
 
+/// Number of floats describing the position of a point
+const POINT_STRIDE: usize = 2;
+
+/// Returns how many points are described by a slice of interleaved xy positions
+fn point_count(positions: &[f32]) -> Result<usize, String> {
+    if !positions.len().is_multiple_of(POINT_STRIDE) {
+        return Err(format!(
+            "Positions length {} is not a multiple of {}",
+            positions.len(),
+            POINT_STRIDE
+        ));
+    }
+    Ok(positions.len() / POINT_STRIDE)
+}
+
 struct DefaultPipeline {
     program: Program,
     transform_loc: Option<WebGlUniformLocation>,
@@ -316,6 +396,183 @@ impl SelectPipeline {
     }
 }
 
+/// Instanced drawing, which is core in WebGL2 and an extension in WebGL1
+enum Instancing {
+    WebGl2(WebGl2RenderingContext),
+    Angle(AngleInstancedArrays),
+}
+
+impl Instancing {
+    /// Returns `None` when instancing is not supported
+    fn new(gl: &GL, webgl2: bool) -> Option<Self> {
+        if webgl2 {
+            return Some(Instancing::WebGl2(gl.clone().unchecked_into()));
+        }
+
+        match gl.get_extension("ANGLE_instanced_arrays") {
+            Ok(Some(extension)) => Some(Instancing::Angle(extension.unchecked_into())),
+            _ => None,
+        }
+    }
+
+    /// Sets how many instances share each value of an attribute, 0 meaning one per vertex
+    fn vertex_attrib_divisor(&self, index: u32, divisor: u32) {
+        match self {
+            Instancing::WebGl2(gl) => gl.vertex_attrib_divisor(index, divisor),
+            Instancing::Angle(ext) => ext.vertex_attrib_divisor_angle(index, divisor),
+        }
+    }
+
+    /// Draws a number of instances of a primitive with a single draw call
+    fn draw(&self, primitive: &Primitive, instances: i32) {
+        match self {
+            Instancing::WebGl2(gl) => gl.draw_elements_instanced_with_i32(
+                primitive.mode,
+                primitive.index_count,
+                GL::UNSIGNED_BYTE,
+                0,
+                instances,
+            ),
+            Instancing::Angle(ext) => ext.draw_elements_instanced_angle_with_i32(
+                primitive.mode,
+                primitive.index_count,
+                GL::UNSIGNED_BYTE,
+                0,
+                instances,
+            ),
+        }
+    }
+}
+
+/// Default pipeline taking model transforms from a per-instance attribute
+struct InstancedPipeline {
+    pipeline: DefaultPipeline,
+    /// Location of the first column of the transform matrix
+    transform_loc: i32,
+    instance_buffer: Option<WebGlBuffer>,
+}
+
+impl InstancedPipeline {
+    fn new(gl: &GL) -> Self {
+        let vert_src = include_str!("../res/shader/instanced.vert.glsl");
+        let frag_src = include_str!("../res/shader/default.frag.glsl");
+        let pipeline = DefaultPipeline::new(gl, vert_src, frag_src);
+
+        let transform_loc = pipeline.program.get_attrib_loc("in_transform");
+        let instance_buffer = gl.create_buffer();
+
+        Self {
+            pipeline,
+            transform_loc,
+            instance_buffer,
+        }
+    }
+
+    /// Uploads one transform per instance and binds them to the transform attribute
+    fn bind_transforms(&self, instancing: &Instancing, transforms: &[f32]) {
+        let gl = &self.pipeline.program.gl;
+        gl.bind_buffer(GL::ARRAY_BUFFER, self.instance_buffer.as_ref());
+        gl.buffer_data_with_array_buffer_view(
+            GL::ARRAY_BUFFER,
+            // The view is consumed before any allocation happens
+            unsafe { &js_sys::Float32Array::view(transforms) },
+            GL::STREAM_DRAW,
+        );
+
+        // A mat4 attribute takes four consecutive locations, one per column
+        let column_size = 4 * std::mem::size_of::<f32>() as i32;
+        for column in 0..4 {
+            let loc = (self.transform_loc + column) as u32;
+            gl.vertex_attrib_pointer_with_i32(
+                loc,
+                4,
+                GL::FLOAT,
+                false,
+                4 * column_size,
+                column * column_size,
+            );
+            gl.enable_vertex_attrib_array(loc);
+            instancing.vertex_attrib_divisor(loc, 1);
+        }
+    }
+
+    /// Restores the transform attribute locations for pipelines which are not instanced
+    fn unbind_transforms(&self, instancing: &Instancing) {
+        for column in 0..4 {
+            let loc = (self.transform_loc + column) as u32;
+            instancing.vertex_attrib_divisor(loc, 0);
+            self.pipeline.program.gl.disable_vertex_attrib_array(loc);
+        }
+    }
+}
+
+/// Groups draws which can be instanced together, as they share primitive and material,
+/// returning the indices of the draws of each batch
+fn batch_instances(keys: &[(PrimitiveKind, Material)]) -> Vec<Vec<usize>> {
+    let mut batches: Vec<((PrimitiveKind, Material), Vec<usize>)> = vec![];
+
+    for (i, key) in keys.iter().enumerate() {
+        match batches.iter_mut().find(|(batch_key, _)| batch_key == key) {
+            Some((_, indices)) => indices.push(i),
+            None => batches.push((*key, vec![i])),
+        }
+    }
+
+    batches.into_iter().map(|(_, indices)| indices).collect()
+}
+
+/// Draws a fullscreen quad blending two colors from bottom to top
+struct GradientPipeline {
+    program: Program,
+    top_color_loc: Option<WebGlUniformLocation>,
+    bottom_color_loc: Option<WebGlUniformLocation>,
+}
+
+impl GradientPipeline {
+    fn new(gl: &GL) -> Self {
+        let vert_src = include_str!("../res/shader/gradient.vert.glsl");
+        let frag_src = include_str!("../res/shader/gradient.frag.glsl");
+        let program = Program::new(gl.clone(), vert_src, frag_src);
+        program.bind();
+
+        let top_color_loc = program.get_uniform_loc("top_color");
+        let bottom_color_loc = program.get_uniform_loc("bottom_color");
+
+        Self {
+            program,
+            top_color_loc,
+            bottom_color_loc,
+        }
+    }
+
+    fn bind_attribs(&self) {
+        let position_loc = self.program.get_attrib_loc("in_position");
+        let stride = std::mem::size_of::<Vertex>() as i32;
+
+        self.program.gl.vertex_attrib_pointer_with_i32(
+            position_loc as u32,
+            3,
+            GL::FLOAT,
+            false,
+            stride,
+            0,
+        );
+        self.program
+            .gl
+            .enable_vertex_attrib_array(position_loc as u32);
+    }
+}
+
+/// Top and bottom colors of a background gradient
+type Gradient = ([f32; 3], [f32; 3]);
+
+/// Converts a slice coming from JS into an RGB color
+fn rgb(color: &[f32]) -> Result<[f32; 3], JsValue> {
+    std::convert::TryFrom::try_from(color).map_err(|_| {
+        JsValue::from_str(&format!("Expected 3 color components, got {}", color.len()))
+    })
+}
+
 #[repr(C)]
 struct Vertex {
     position: [f32; 3], // xy
@@ -562,6 +819,45 @@ impl Geometry {
 
         Self { vertices, indices }
     }
+
+    /// Constructs a line-list of a wireframe grid of unit cells on the XZ plane,
+    /// centered at the origin, with `rows` cells along Z and `cols` cells along X
+    fn grid(rows: u32, cols: u32) -> Self {
+        let half_width = cols as f32 / 2.0;
+        let half_depth = rows as f32 / 2.0;
+
+        let vertex = |x: f32, z: f32| Vertex {
+            position: [x, 0.0, z],
+            color: [0.5, 0.5, 0.5, 1.0],
+            normal: [0.0, 1.0, 0.0],
+            // Samples the white texel of the default texture
+            uv: [0.75, 0.75],
+        };
+
+        let mut vertices = vec![];
+
+        // Lines parallel to the X axis
+        for row in 0..=rows {
+            let z = row as f32 - half_depth;
+            vertices.push(vertex(-half_width, z));
+            vertices.push(vertex(half_width, z));
+        }
+
+        // Lines parallel to the Z axis
+        for col in 0..=cols {
+            let x = col as f32 - half_width;
+            vertices.push(vertex(x, -half_depth));
+            vertices.push(vertex(x, half_depth));
+        }
+
+        assert!(
+            vertices.len() <= u8::MAX as usize + 1,
+            "Grid too big for 8-bit indices"
+        );
+        let indices = (0..vertices.len()).map(|i| i as u8).collect();
+
+        Self { vertices, indices }
+    }
 }
 
 /// GPU-side primitive geometry
@@ -570,10 +866,21 @@ struct Primitive {
     vertex_buffer: Option<WebGlBuffer>,
     index_buffer: Option<WebGlBuffer>,
     index_count: i32,
+    /// How to assemble vertices, e.g. `GL::TRIANGLES` or `GL::LINES`
+    mode: u32,
 }
 
 impl Primitive {
     fn new(gl: GL, geometry: &Geometry) -> Self {
+        Self::with_mode(gl, geometry, GL::TRIANGLES)
+    }
+
+    /// Creates a primitive drawn as a list of lines
+    fn lines(gl: GL, geometry: &Geometry) -> Self {
+        Self::with_mode(gl, geometry, GL::LINES)
+    }
+
+    fn with_mode(gl: GL, geometry: &Geometry, mode: u32) -> Self {
         let vertex_buffer = gl.create_buffer();
         gl.bind_buffer(GL::ARRAY_BUFFER, vertex_buffer.as_ref());
         gl.buffer_data_with_array_buffer_view(
@@ -592,6 +899,7 @@ impl Primitive {
             vertex_buffer,
             index_buffer,
             index_count,
+            mode,
         }
     }
 
@@ -604,7 +912,7 @@ impl Primitive {
 
     fn draw(&self) {
         self.gl
-            .draw_elements_with_i32(GL::TRIANGLES, self.index_count, GL::UNSIGNED_BYTE, 0);
+            .draw_elements_with_i32(self.mode, self.index_count, GL::UNSIGNED_BYTE, 0);
     }
 }
 
@@ -618,13 +926,23 @@ impl Drop for Primitive {
 struct Texture {
     gl: GL,
     handle: WebGlTexture,
+    /// Width and height of the last uploaded image
+    size: Cell<(u32, u32)>,
+    mipmaps: Cell<bool>,
 }
 
 impl Texture {
-    fn new(gl: GL) -> Self {
-        let handle = gl.create_texture().expect("Failed to create texture");
+    fn new(gl: GL) -> Result<Self, JsValue> {
+        let handle = gl
+            .create_texture()
+            .or_js_error("Failed to create texture")?;
 
-        let texture = Self { gl, handle };
+        let texture = Self {
+            gl,
+            handle,
+            size: Cell::new((0, 0)),
+            mipmaps: Cell::new(false),
+        };
 
         texture.bind();
 
@@ -635,12 +953,12 @@ impl Texture {
             .gl
             .tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MAG_FILTER, GL::NEAREST as i32);
 
-        let pixels = [
+        let pixels = vec![
             255u8, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255, 255, 255, 255, 255,
         ];
-        texture.upload(2, 2, &pixels);
+        texture.upload(&Image::from_raw(2, 2, pixels));
 
-        texture
+        Ok(texture)
     }
 
     fn bind(&self) {
@@ -648,21 +966,60 @@ impl Texture {
         self.gl.bind_texture(GL::TEXTURE_2D, Some(&self.handle));
     }
 
-    /// Uploads pixels data to the texture memory in the GPU
-    fn upload(&self, width: u32, height: u32, pixels: &[u8]) {
+    /// Uploads image pixels to the texture memory in the GPU
+    fn upload(&self, image: &Image) {
         self.gl
             .tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
                 GL::TEXTURE_2D,
                 0,
                 GL::RGBA as i32,
-                width as i32,
-                height as i32,
+                image.width as i32,
+                image.height as i32,
                 0,
                 GL::RGBA,
                 GL::UNSIGNED_BYTE,
-                Some(&pixels),
+                Some(&image.pixels),
             )
             .expect("Failed to upload texture data");
+
+        self.size.set((image.width, image.height));
+        if self.mipmaps.get() {
+            self.gl.generate_mipmap(GL::TEXTURE_2D);
+        }
+    }
+
+    /// Switches between nearest and linear filtering, optionally sampling from mipmaps
+    fn set_filtering(&self, linear: bool, mipmaps: bool, webgl2: bool) -> Result<(), JsValue> {
+        let (width, height) = self.size.get();
+        // WebGL1 can only generate mipmaps for power-of-two textures, WebGL2 for any size
+        let power_of_two = width.is_power_of_two() && height.is_power_of_two();
+        if mipmaps && !webgl2 && !power_of_two {
+            return Err(JsValue::from_str(&format!(
+                "Mipmaps require power-of-two dimensions on WebGL1, texture is {}x{}",
+                width, height
+            )));
+        }
+
+        self.bind();
+
+        let (min_filter, mag_filter) = match (linear, mipmaps) {
+            (true, true) => (GL::LINEAR_MIPMAP_LINEAR, GL::LINEAR),
+            (true, false) => (GL::LINEAR, GL::LINEAR),
+            (false, true) => (GL::NEAREST_MIPMAP_NEAREST, GL::NEAREST),
+            (false, false) => (GL::NEAREST, GL::NEAREST),
+        };
+
+        if mipmaps {
+            self.gl.generate_mipmap(GL::TEXTURE_2D);
+        }
+        self.mipmaps.set(mipmaps);
+
+        self.gl
+            .tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MIN_FILTER, min_filter as i32);
+        self.gl
+            .tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MAG_FILTER, mag_filter as i32);
+
+        Ok(())
     }
 }
 
@@ -672,22 +1029,94 @@ impl Drop for Texture {
     }
 }
 
+/// Kinds of primitive geometry a node can be made of
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum PrimitiveKind {
+    Triangle,
+    Quad,
+    Cube,
+    Grid { rows: u32, cols: u32 },
+}
+
+impl PrimitiveKind {
+    /// Creates the GPU-side primitive for this kind of geometry
+    fn create(self, gl: GL) -> Primitive {
+        match self {
+            PrimitiveKind::Triangle => Primitive::new(gl, &Geometry::triangle()),
+            PrimitiveKind::Quad => Primitive::new(gl, &Geometry::quad()),
+            PrimitiveKind::Cube => Primitive::new(gl, &Geometry::cube()),
+            PrimitiveKind::Grid { rows, cols } => Primitive::lines(gl, &Geometry::grid(rows, cols)),
+        }
+    }
+
+    /// Checks that the geometry can be created, as grids are limited by their 8-bit indices
+    fn validate(self) -> Result<(), String> {
+        match self {
+            PrimitiveKind::Grid { rows, cols } => {
+                // Two vertices for each of the rows + 1 and cols + 1 lines
+                let vertex_count = (rows as u64 + 1 + cols as u64 + 1) * 2;
+                if vertex_count > u8::MAX as u64 + 1 {
+                    return Err(format!("Grid {}x{} too big for 8-bit indices", rows, cols));
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Coefficients scaling how a surface responds to each lighting term
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct Material {
+    ambient: f32,
+    diffuse: f32,
+    specular: f32,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            ambient: 0.1,
+            diffuse: 1.0,
+            specular: 1.0,
+        }
+    }
+}
+
 struct Node {
     id: u32,
     transform: Isometry3<f32>,
+    kind: PrimitiveKind,
     primitive: Primitive,
+    material: Material,
+    /// Whether the automatic scene rotation moves this node
+    spin: bool,
     children: Vec<Node>,
 }
 
 impl Node {
-    fn new(primitive: Primitive) -> Self {
+    fn new(gl: GL, kind: PrimitiveKind) -> Self {
         Self {
             id: 0,
             transform: Isometry3::identity(),
-            primitive,
+            kind,
+            primitive: kind.create(gl),
+            material: Material::default(),
+            spin: true,
             children: vec![],
         }
     }
+
+    /// Looks for the node with a certain id within this node hierarchy
+    fn find_mut(&mut self, id: u32) -> Option<&mut Node> {
+        if self.id == id {
+            return Some(self);
+        }
+
+        self.children
+            .iter_mut()
+            .find_map(|child| child.find_mut(id))
+    }
 }
 
 struct Mouse {
@@ -708,12 +1137,164 @@ impl Mouse {
     }
 }
 
+/// Returns the view the camera starts with, looking at the origin from the positive Z axis
+fn initial_view() -> Isometry3<f32> {
+    // OpenGL uses a right-handed coordinate system
+    Isometry3::look_at_rh(
+        &Point3::new(0.0, 0.0, 12.0),
+        &Point3::origin(),
+        &Vector3::y_axis(),
+    )
+}
+
+/// Moves the view back to `initial_view`, undoing any pan, zoom, or orbit
+fn reset_view(view: &RefCell<Isometry3<f32>>) {
+    *view.borrow_mut() = initial_view();
+}
+
+/// Parameters of the camera projection
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Lens {
+    /// Vertical field of view in radians
+    fov: f32,
+    near: f32,
+    far: f32,
+}
+
+impl Default for Lens {
+    fn default() -> Self {
+        Self {
+            fov: std::f32::consts::FRAC_PI_4,
+            near: 0.125,
+            far: 256.0,
+        }
+    }
+}
+
+impl Lens {
+    fn set_fov(&mut self, radians: f32) -> Result<(), String> {
+        if !(radians > 0.0 && radians < std::f32::consts::PI) {
+            return Err(format!("Field of view {} is not within (0, PI)", radians));
+        }
+        self.fov = radians;
+        Ok(())
+    }
+
+    fn set_near(&mut self, near: f32) -> Result<(), String> {
+        if !(near > 0.0 && near < self.far) {
+            return Err(format!(
+                "Near plane {} is not within (0, {})",
+                near, self.far
+            ));
+        }
+        self.near = near;
+        Ok(())
+    }
+
+    fn set_far(&mut self, far: f32) -> Result<(), String> {
+        if far.is_nan() || far <= self.near {
+            return Err(format!(
+                "Far plane {} is not beyond near plane {}",
+                far, self.near
+            ));
+        }
+        self.far = far;
+        Ok(())
+    }
+}
+
+/// Half the height of the orthographic view volume, which roughly matches
+/// what the perspective projection shows at the initial camera distance
+const ORTHO_HALF_HEIGHT: f32 = 5.0;
+
+/// Returns a projection matrix for a viewport with the given aspect ratio
+fn projection(aspect: f32, ortho: bool, lens: &Lens) -> Matrix4<f32> {
+    if ortho {
+        let half_width = ORTHO_HALF_HEIGHT * aspect;
+        nalgebra::Orthographic3::new(
+            -half_width,
+            half_width,
+            -ORTHO_HALF_HEIGHT,
+            ORTHO_HALF_HEIGHT,
+            lens.near,
+            lens.far,
+        )
+        .to_homogeneous()
+    } else {
+        nalgebra::Perspective3::new(aspect, lens.fov, lens.near, lens.far).to_homogeneous()
+    }
+}
+
+/// Returns how the view should move for a key press, relative to the camera orientation
+fn key_translation(key: &str, speed: f32) -> Option<Translation3<f32>> {
+    // The view transforms the world into camera space, hence
+    // moving the camera one way moves the world the other way
+    let (x, y, z) = match key {
+        "w" | "W" => (0.0, 0.0, speed),
+        "s" | "S" => (0.0, 0.0, -speed),
+        "a" | "A" => (speed, 0.0, 0.0),
+        "d" | "D" => (-speed, 0.0, 0.0),
+        "q" | "Q" => (0.0, -speed, 0.0),
+        "e" | "E" => (0.0, speed, 0.0),
+        _ => return None,
+    };
+    Some(Translation3::new(x, y, z))
+}
+
+/// Returns the automatic rotation of the scene at a certain time, orbiting around the pivot
+fn auto_spin(now: f32, pivot: &Point3<f32>) -> Isometry3<f32> {
+    let rotation = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), now / 4096.0)
+        * UnitQuaternion::from_axis_angle(&Vector3::z_axis(), now / 4096.0);
+    Isometry3::rotation_wrt_point(rotation, *pivot)
+}
+
+/// Returns the final transform of a node, which follows the spin only when enabled
+fn spin_transform(
+    node: &Node,
+    transform: &Isometry3<f32>,
+    spin: &Isometry3<f32>,
+) -> Isometry3<f32> {
+    if node.spin {
+        spin * transform
+    } else {
+        *transform
+    }
+}
+
+/// Flattens a node hierarchy into a list of nodes with their final transforms
+fn collect_draws<'a>(
+    node: &'a Node,
+    parent_trs: &Isometry3<f32>,
+    spin: &Isometry3<f32>,
+    draws: &mut Vec<(&'a Node, Isometry3<f32>)>,
+) {
+    let transform = parent_trs * node.transform;
+    draws.push((node, spin_transform(node, &transform, spin)));
+
+    for child in &node.children {
+        collect_draws(child, &transform, spin, draws);
+    }
+}
+
+/// Returns the indices of the transforms sorted from the farthest to the closest to the camera
+fn back_to_front(view: &Isometry3<f32>, transforms: &[Isometry3<f32>]) -> Vec<usize> {
+    // The camera looks down the negative Z axis of view space
+    let depth = |i: usize| (view * transforms[i]).translation.z;
+
+    let mut order: Vec<usize> = (0..transforms.len()).collect();
+    order.sort_by(|&a, &b| depth(a).total_cmp(&depth(b)));
+    order
+}
+
 #[wasm_bindgen]
 pub struct Context {
     performance: web_sys::Performance,
     canvas: HtmlCanvasElement,
     gl: WebGlRenderingContext,
+    /// Whether features beyond WebGL1 are available
+    webgl2: bool,
     view: Rc<RefCell<Isometry3<f32>>>,
+    move_speed: Rc<Cell<f32>>,
     mouse: Rc<RefCell<Mouse>>,
     offscreen_framebuffer: Option<WebGlFramebuffer>,
     offscreen_colorbuffer: Option<WebGlRenderbuffer>,
@@ -721,8 +1302,24 @@ pub struct Context {
     point_pipeline: PointPipeline,
     default_pipeline: DefaultPipeline,
     select_pipeline: SelectPipeline,
+    instanced_pipeline: InstancedPipeline,
+    /// Available when the context supports instanced drawing
+    instancing: Option<Instancing>,
+    use_instancing: bool,
+    gradient_pipeline: GradientPipeline,
+    background_quad: Primitive,
+    background: Option<Gradient>,
     nodes: Vec<Node>,
     texture: Texture,
+    grid: Node,
+    show_grid: bool,
+    gamma_correction: bool,
+    shininess: f32,
+    ortho: bool,
+    lens: Lens,
+    blending: bool,
+    /// Point the automatic scene rotation orbits around
+    spin_pivot: Point3<f32>,
 }
 
 fn create_point_program(gl: &WebGlRenderingContext) -> PointPipeline {
@@ -753,14 +1350,83 @@ fn generate_node_colors(
     }
 }
 
+/// Handle to a running render loop
+#[wasm_bindgen]
+pub struct RenderLoop {
+    running: Rc<Cell<bool>>,
+}
+
+#[wasm_bindgen]
+impl RenderLoop {
+    /// Stops the loop before the next frame is drawn
+    pub fn stop(&self) {
+        self.running.set(false);
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.get()
+    }
+}
+
+/// Options for creating a context, with antialiasing enabled by default
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContextBuilder {
+    canvas_id: String,
+    antialias: bool,
+}
+
+impl Default for ContextBuilder {
+    fn default() -> Self {
+        Self {
+            canvas_id: DEFAULT_CANVAS_ID.to_string(),
+            antialias: true,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl ContextBuilder {
+    pub fn new() -> ContextBuilder {
+        Self::default()
+    }
+
+    /// Sets the id of the canvas element to render to
+    pub fn canvas_id(mut self, id: &str) -> ContextBuilder {
+        self.canvas_id = id.to_string();
+        self
+    }
+
+    /// Multisampling smooths edges, but it can be disabled to save fill rate
+    pub fn antialias(mut self, enabled: bool) -> ContextBuilder {
+        self.antialias = enabled;
+        self
+    }
+
+    pub fn build(&self) -> Result<Context, JsValue> {
+        Context::with_options(self)
+    }
+}
+
 #[wasm_bindgen]
 impl Context {
     pub fn new() -> Result<Context, JsValue> {
-        let window = web_sys::window().unwrap();
-        let performance = window.performance().unwrap();
+        ContextBuilder::new().build()
+    }
+
+    /// Creates a context rendering to the canvas with the given element id
+    pub fn new_with_canvas_id(id: &str) -> Result<Context, JsValue> {
+        ContextBuilder::new().canvas_id(id).build()
+    }
 
-        let canvas = get_canvas()?;
-        let gl = get_gl_context(&canvas)?;
+    fn with_options(options: &ContextBuilder) -> Result<Context, JsValue> {
+        let window = web_sys::window().or_js_error("Global window not available")?;
+        let performance = window
+            .performance()
+            .or_js_error("Performance timer not available")?;
+
+        let canvas = get_canvas(&options.canvas_id)?;
+        let (gl, webgl2) = get_gl_context(&canvas, options.antialias)?;
 
         let offscreen_framebuffer = gl.create_framebuffer();
         gl.bind_framebuffer(GL::FRAMEBUFFER, offscreen_framebuffer.as_ref());
@@ -800,29 +1466,26 @@ impl Context {
         let point_pipeline = create_point_program(&gl);
         let default_pipeline = create_default_program(&gl);
         let mut select_pipeline = SelectPipeline::new(&gl);
+        let instanced_pipeline = InstancedPipeline::new(&gl);
+        let instancing = Instancing::new(&gl, webgl2);
+        let gradient_pipeline = GradientPipeline::new(&gl);
+        let background_quad = Primitive::new(gl.clone(), &Geometry::quad());
 
-        // OpenGL uses a right-handed coordinate system
-        let view = Rc::new(RefCell::new(Isometry3::look_at_rh(
-            &Point3::new(0.0, 0.0, 12.0),
-            &Point3::origin(),
-            &Vector3::y_axis(),
-        )));
+        let view = Rc::new(RefCell::new(initial_view()));
 
         let mut nodes = vec![];
 
-        let cube = Geometry::cube();
-
-        let mut root = Node::new(Primitive::new(gl.clone(), &cube));
+        let mut root = Node::new(gl.clone(), PrimitiveKind::Cube);
         root.transform
             .append_translation_mut(&Translation3::new(0.0, 0.0, 0.0));
 
-        let mut node_right = Node::new(Primitive::new(gl.clone(), &cube));
+        let mut node_right = Node::new(gl.clone(), PrimitiveKind::Cube);
         node_right.id = 1;
         node_right
             .transform
             .append_translation_mut(&Translation3::new(1.5, 0.0, 0.0));
 
-        let mut node_left = Node::new(Primitive::new(gl.clone(), &cube));
+        let mut node_left = Node::new(gl.clone(), PrimitiveKind::Cube);
         node_left.id = 2;
         node_left
             .transform
@@ -837,13 +1500,22 @@ impl Context {
 
         nodes.push(root);
 
-        let texture = Texture::new(gl.clone());
+        let texture = Texture::new(gl.clone())?;
+
+        // Reference floor beneath the scene, which is not selectable
+        let mut grid = Node::new(gl.clone(), PrimitiveKind::Grid { rows: 16, cols: 16 });
+        grid.id = u32::MAX;
+        grid.spin = false;
+        grid.transform
+            .append_translation_mut(&Translation3::new(0.0, -2.0, 0.0));
 
         let ret = Context {
             performance,
             canvas,
             gl,
+            webgl2,
             view,
+            move_speed: Rc::new(Cell::new(0.25)),
             mouse: Rc::new(RefCell::new(Mouse::new())),
             offscreen_framebuffer,
             offscreen_colorbuffer,
@@ -851,14 +1523,29 @@ impl Context {
             point_pipeline,
             default_pipeline,
             select_pipeline,
+            instanced_pipeline,
+            instancing,
+            use_instancing: true,
+            gradient_pipeline,
+            background_quad,
+            background: None,
             nodes,
             texture,
+            grid,
+            show_grid: false,
+            gamma_correction: false,
+            shininess: 32.0,
+            ortho: false,
+            lens: Lens::default(),
+            blending: false,
+            spin_pivot: Point3::origin(),
         };
 
-        let document = window.document().unwrap();
+        let document = window.document().or_js_error("Window has no document")?;
         ret.set_onmousemove(&document);
         ret.set_onwheel(&document);
         ret.set_onmouseclick(&document);
+        ret.set_onkeydown(&document);
 
         Ok(ret)
     }
@@ -936,16 +1623,62 @@ impl Context {
         closure.forget();
     }
 
-    /// Draws a point at position x and y
+    fn set_onkeydown(&self, document: &Document) {
+        let view = self.view.clone();
+        let move_speed = self.move_speed.clone();
+        let callback = Box::new(move |e: web_sys::KeyboardEvent| {
+            // First-person camera movement
+            if let Some(translation) = key_translation(&e.key(), move_speed.get()) {
+                view.borrow_mut().append_translation_mut(&translation);
+            }
+        });
+        let closure = wasm_bindgen::closure::Closure::wrap(
+            callback as Box<dyn FnMut(web_sys::KeyboardEvent)>,
+        );
+        document.set_onkeydown(Some(closure.as_ref().unchecked_ref()));
+        closure.forget();
+    }
+
+    /// Moves the camera back to where it started, undoing any pan, zoom, or orbit
+    pub fn reset_camera(&mut self) {
+        reset_view(&self.view);
+    }
+
+    /// Sets how far the camera moves for each WASD/QE key press
+    pub fn set_move_speed(&mut self, units_per_key: f32) {
+        self.move_speed.set(units_per_key);
+    }
+
+    /// Returns whether the context is WebGL2, otherwise it is WebGL1
+    pub fn is_webgl2(&self) -> bool {
+        self.webgl2
+    }
+
+    /// Draws a green point of 16 pixels at position x and y
     pub fn draw_point(&self, x: f32, y: f32) -> Result<(), JsValue> {
+        self.draw_point_with_style(x, y, 16.0, 0.0, 1.0, 0.0, 1.0)
+    }
+
+    /// Draws a point at position x and y with the given size in pixels and RGBA color
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_point_with_style(
+        &self,
+        x: f32,
+        y: f32,
+        size: f32,
+        r: f32,
+        g: f32,
+        b: f32,
+        a: f32,
+    ) -> Result<(), JsValue> {
         self.point_pipeline.program.bind();
 
         self.gl
-            .vertex_attrib1f(self.point_pipeline.point_size_loc as u32, 16.0);
+            .vertex_attrib1f(self.point_pipeline.point_size_loc as u32, size);
         self.gl
             .vertex_attrib3f(self.point_pipeline.position_loc as u32, x, y, 0.0);
         self.gl
-            .uniform4f(self.point_pipeline.color_loc.as_ref(), 0.0, 1.0, 0.0, 1.0);
+            .uniform4f(self.point_pipeline.color_loc.as_ref(), r, g, b, a);
 
         self.gl.clear_color(0.0, 0.0, 0.0, 1.0);
         self.gl.clear(GL::COLOR_BUFFER_BIT);
@@ -955,6 +1688,264 @@ impl Context {
         Ok(())
     }
 
+    /// Draws green points at the interleaved xy positions with a single draw call
+    pub fn draw_points(&self, positions: &[f32], point_size: f32) -> Result<(), JsValue> {
+        let count = point_count(positions).map_err(|err| JsValue::from_str(&err))?;
+
+        let pipeline = &self.point_pipeline;
+        pipeline.program.bind();
+
+        self.gl
+            .bind_buffer(GL::ARRAY_BUFFER, pipeline.position_buffer.as_ref());
+        self.gl.buffer_data_with_array_buffer_view(
+            GL::ARRAY_BUFFER,
+            // The view is consumed before any allocation happens
+            unsafe { &js_sys::Float32Array::view(positions) },
+            GL::STREAM_DRAW,
+        );
+
+        let position_loc = pipeline.position_loc as u32;
+        self.gl.vertex_attrib_pointer_with_i32(
+            position_loc,
+            POINT_STRIDE as i32,
+            GL::FLOAT,
+            false,
+            0,
+            0,
+        );
+        self.gl.enable_vertex_attrib_array(position_loc);
+
+        self.gl
+            .vertex_attrib1f(pipeline.point_size_loc as u32, point_size);
+        self.gl
+            .uniform4f(pipeline.color_loc.as_ref(), 0.0, 1.0, 0.0, 1.0);
+
+        self.gl.clear_color(0.0, 0.0, 0.0, 1.0);
+        self.gl.clear(GL::COLOR_BUFFER_BIT);
+
+        self.gl.draw_arrays(GL::POINTS, 0, count as i32);
+
+        // Single points read their position from a constant attribute
+        self.gl.disable_vertex_attrib_array(position_loc);
+
+        Ok(())
+    }
+
+    /// Shows or hides a reference grid beneath the scene
+    pub fn show_grid(&mut self, on: bool) {
+        self.show_grid = on;
+    }
+
+    /// Enables or disables gamma correction of the default pipeline output
+    pub fn set_gamma_correction(&mut self, on: bool) {
+        self.gamma_correction = on;
+    }
+
+    /// Sets the specular exponent of the default pipeline, higher values give smaller highlights
+    pub fn set_shininess(&mut self, s: f32) {
+        self.shininess = s;
+    }
+
+    fn find_node_mut(&mut self, id: u32) -> Result<&mut Node, JsValue> {
+        self.nodes
+            .iter_mut()
+            .find_map(|node| node.find_mut(id))
+            .ok_or_else(|| JsValue::from_str(&format!("Node {} not found", id)))
+    }
+
+    /// Rotates the node with the given id in place around the axis
+    pub fn rotate_node(
+        &mut self,
+        id: u32,
+        axis_x: f32,
+        axis_y: f32,
+        axis_z: f32,
+        radians: f32,
+    ) -> Result<(), JsValue> {
+        let axis = nalgebra::Unit::try_new(Vector3::new(axis_x, axis_y, axis_z), f32::EPSILON)
+            .ok_or_else(|| JsValue::from_str("Rotation axis must not be zero"))?;
+        let rotation = UnitQuaternion::from_axis_angle(&axis, radians);
+
+        let node = self.find_node_mut(id)?;
+        node.transform.append_rotation_wrt_center_mut(&rotation);
+
+        Ok(())
+    }
+
+    /// Sets whether the automatic rotation moves the node with the given id
+    pub fn set_node_spin(&mut self, id: u32, enabled: bool) -> Result<(), JsValue> {
+        self.find_node_mut(id)?.spin = enabled;
+        Ok(())
+    }
+
+    /// Sets the point the automatic rotation orbits around
+    pub fn set_spin_pivot(&mut self, x: f32, y: f32, z: f32) {
+        self.spin_pivot = Point3::new(x, y, z);
+    }
+
+    /// Sets the lighting coefficients of the node with the given id
+    pub fn set_node_material(
+        &mut self,
+        id: u32,
+        ambient: f32,
+        diffuse: f32,
+        specular: f32,
+    ) -> Result<(), JsValue> {
+        let node = self.find_node_mut(id)?;
+        node.material = Material {
+            ambient,
+            diffuse,
+            specular,
+        };
+
+        Ok(())
+    }
+
+    /// Draws a vertical gradient behind the scene instead of the solid clear color
+    pub fn set_gradient_background(&mut self, top: &[f32], bottom: &[f32]) -> Result<(), JsValue> {
+        self.background = Some((rgb(top)?, rgb(bottom)?));
+        Ok(())
+    }
+
+    /// Goes back to clearing the background with a solid color
+    pub fn reset_background(&mut self) {
+        self.background = None;
+    }
+
+    /// Draws the background gradient, if any, without touching the depth buffer
+    fn draw_background(&self) {
+        let (top, bottom) = match self.background {
+            Some(gradient) => gradient,
+            None => return,
+        };
+
+        self.gl.disable(GL::DEPTH_TEST);
+
+        self.gradient_pipeline.program.bind();
+        self.background_quad.bind();
+        self.gradient_pipeline.bind_attribs();
+
+        self.gl
+            .uniform3fv_with_f32_array(self.gradient_pipeline.top_color_loc.as_ref(), &top);
+        self.gl
+            .uniform3fv_with_f32_array(self.gradient_pipeline.bottom_color_loc.as_ref(), &bottom);
+
+        self.background_quad.draw();
+
+        self.gl.enable(GL::DEPTH_TEST);
+    }
+
+    /// Enables or disables instanced drawing, which is only used when supported
+    pub fn set_instancing(&mut self, on: bool) {
+        self.use_instancing = on;
+    }
+
+    /// Enables or disables alpha blending, drawing nodes from back to front when on
+    pub fn set_blending(&mut self, on: bool) {
+        self.blending = on;
+    }
+
+    /// Sets the texture filtering of the cubes, nearest without mipmaps by default
+    pub fn set_texture_filtering(&self, linear: bool, mipmaps: bool) -> Result<(), JsValue> {
+        self.texture.set_filtering(linear, mipmaps, self.webgl2)
+    }
+
+    /// Switches between perspective and orthographic projection
+    pub fn set_projection_mode(&mut self, ortho: bool) {
+        self.ortho = ortho;
+    }
+
+    /// Sets the vertical field of view of the perspective projection
+    pub fn set_fov(&mut self, radians: f32) -> Result<(), JsValue> {
+        self.lens
+            .set_fov(radians)
+            .map_err(|err| JsValue::from_str(&err))
+    }
+
+    /// Sets the distance of the near clipping plane, which must be positive
+    pub fn set_near(&mut self, near: f32) -> Result<(), JsValue> {
+        self.lens
+            .set_near(near)
+            .map_err(|err| JsValue::from_str(&err))
+    }
+
+    /// Sets the distance of the far clipping plane, which must be beyond the near one
+    pub fn set_far(&mut self, far: f32) -> Result<(), JsValue> {
+        self.lens
+            .set_far(far)
+            .map_err(|err| JsValue::from_str(&err))
+    }
+
+    /// Returns the projection matrix for the current canvas size and projection mode
+    fn proj(&self) -> Matrix4<f32> {
+        let width = self.canvas.width() as f32;
+        let height = self.canvas.height() as f32;
+        projection(width / height, self.ortho, &self.lens)
+    }
+
+    /// Returns the scene graph as JSON, without any GPU resource
+    pub fn export_scene(&self) -> String {
+        let scene: Vec<scene::SceneNode> = self.nodes.iter().map(scene::SceneNode::from).collect();
+        serde_json::to_string(&scene).expect("Failed to serialize scene")
+    }
+
+    /// Replaces the scene graph with one exported by `export_scene`
+    pub fn import_scene(&mut self, json: &str) -> Result<(), JsValue> {
+        let scene = scene::parse_scene(json)
+            .map_err(|err| JsValue::from_str(&format!("Failed to parse scene: {}", err)))?;
+
+        self.nodes = scene
+            .into_iter()
+            .map(|node| node.into_node(&self.gl))
+            .collect();
+
+        // New nodes need their own select colors
+        self.select_pipeline.node_colors.clear();
+        let mut rng = rand::thread_rng();
+        for node in &self.nodes {
+            generate_node_colors(&mut self.select_pipeline, &mut rng, node);
+        }
+        self.mouse.borrow_mut().selected_node = None;
+
+        Ok(())
+    }
+
+    /// Keeps drawing the scene on every animation frame until the returned loop is stopped
+    pub fn start_render_loop(self) -> Result<RenderLoop, JsValue> {
+        let window = web_sys::window().or_js_error("Global window not available")?;
+        let running = Rc::new(Cell::new(true));
+
+        // The closure needs a handle to itself to request the next frame
+        let frame = Rc::new(RefCell::new(None::<Closure<dyn FnMut()>>));
+        let next_frame = frame.clone();
+        let frame_window = window.clone();
+        let frame_running = running.clone();
+
+        let callback = Box::new(move || {
+            if !frame_running.get() {
+                // Dropping the closure releases the context it owns
+                let _ = next_frame.borrow_mut().take();
+                return;
+            }
+
+            if let Err(err) = self.draw_primitive() {
+                log!("Render loop stopped: {:?}", err);
+                frame_running.set(false);
+            }
+
+            if let Some(closure) = next_frame.borrow().as_ref() {
+                let _ = frame_window.request_animation_frame(closure.as_ref().unchecked_ref());
+            }
+        });
+        *frame.borrow_mut() = Some(Closure::wrap(callback as Box<dyn FnMut()>));
+
+        if let Some(closure) = frame.borrow().as_ref() {
+            window.request_animation_frame(closure.as_ref().unchecked_ref())?;
+        }
+
+        Ok(RenderLoop { running })
+    }
+
     /// Draws a primitive
     pub fn draw_primitive(&self) -> Result<(), JsValue> {
         self.gl.enable(GL::DEPTH_TEST);
@@ -993,10 +1984,67 @@ impl Context {
             }
         }
 
+        self.gl.clear_color(0.0, 0.0, 0.0, 1.0);
+        self.gl.clear(GL::COLOR_BUFFER_BIT);
+        self.draw_background();
+
         self.default_pipeline.program.bind();
+        self.set_frame_uniforms(&self.default_pipeline.program);
+
+        // Time
+        let now = self.performance.now();
+        let spin = auto_spin(now as f32, &self.spin_pivot);
+        let root = Isometry3::identity();
+
+        if self.blending {
+            self.gl.enable(GL::BLEND);
+            self.gl.blend_func(GL::SRC_ALPHA, GL::ONE_MINUS_SRC_ALPHA);
 
+            // The grid is opaque, so it goes first
+            if self.show_grid {
+                self.draw_node(&self.grid, &root, &spin);
+            }
+
+            // Transparent surfaces composite correctly only from back to front
+            let mut draws = vec![];
+            for node in &self.nodes {
+                collect_draws(node, &root, &spin, &mut draws);
+            }
+            let transforms: Vec<_> = draws.iter().map(|(_, transform)| *transform).collect();
+            for i in back_to_front(&self.view.borrow(), &transforms) {
+                let (node, transform) = &draws[i];
+                self.draw_single_node(node, transform);
+            }
+
+            self.gl.disable(GL::BLEND);
+        } else if let (true, Some(instancing)) = (self.use_instancing, &self.instancing) {
+            let mut draws = vec![];
+            for node in &self.nodes {
+                collect_draws(node, &root, &spin, &mut draws);
+            }
+            self.draw_instanced(instancing, &draws);
+
+            if self.show_grid {
+                self.draw_node(&self.grid, &root, &spin);
+            }
+        } else {
+            // Draw all nodes
+            for node in &self.nodes {
+                self.draw_node(node, &root, &spin);
+            }
+
+            if self.show_grid {
+                self.draw_node(&self.grid, &root, &spin);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sets the uniforms which are the same for all the nodes of a frame
+    fn set_frame_uniforms(&self, program: &Program) {
         // View
-        let view_loc = self.default_pipeline.program.get_uniform_loc("view");
+        let view_loc = program.get_uniform_loc("view");
 
         self.gl.uniform_matrix4fv_with_f32_array(
             view_loc.as_ref(),
@@ -1005,72 +2053,111 @@ impl Context {
         );
 
         // Proj
-        let proj_loc = self.default_pipeline.program.get_uniform_loc("proj");
-
-        let width = self.canvas.width() as f32;
-        let height = self.canvas.height() as f32;
-        let proj = nalgebra::Perspective3::new(width / height, 3.14 / 4.0, 0.125, 256.0);
-        self.gl.uniform_matrix4fv_with_f32_array(
-            proj_loc.as_ref(),
-            false,
-            proj.to_homogeneous().as_slice(),
-        );
+        let proj_loc = program.get_uniform_loc("proj");
+        self.gl
+            .uniform_matrix4fv_with_f32_array(proj_loc.as_ref(), false, self.proj().as_slice());
 
         // Lighting
-        let light_color_loc = self.default_pipeline.program.get_uniform_loc("light_color");
+        let light_color_loc = program.get_uniform_loc("light_color");
         self.gl.uniform3f(light_color_loc.as_ref(), 1.0, 1.0, 1.0);
 
-        let light_position_loc = self
-            .default_pipeline
-            .program
-            .get_uniform_loc("light_position");
+        let light_position_loc = program.get_uniform_loc("light_position");
         self.gl
             .uniform3f(light_position_loc.as_ref(), 4.0, 1.0, 1.0);
 
+        let gamma_correct_loc = program.get_uniform_loc("gamma_correct");
+        self.gl
+            .uniform1i(gamma_correct_loc.as_ref(), self.gamma_correction as i32);
+
+        let shininess_loc = program.get_uniform_loc("shininess");
+        self.gl.uniform1f(shininess_loc.as_ref(), self.shininess);
+
         // Texture
         self.texture.bind();
-        let sampler_loc = self.default_pipeline.program.get_uniform_loc("tex_sampler");
+        let sampler_loc = program.get_uniform_loc("tex_sampler");
         self.gl.uniform1i(sampler_loc.as_ref(), 0);
+    }
 
-        self.gl.clear_color(0.0, 0.0, 0.0, 1.0);
-        self.gl.clear(GL::COLOR_BUFFER_BIT);
+    /// Sets the uniforms describing the surface of a node
+    fn set_node_uniforms(&self, program: &Program, material: &Material, selected: bool) {
+        // Select color
+        let select_color_loc = program.get_uniform_loc("select_color");
+        let select_color = if selected {
+            [0.4f32, 0.4, 0.1, 0.0]
+        } else {
+            [0.0f32, 0.0, 0.0, 0.0]
+        };
+        self.gl
+            .uniform4fv_with_f32_array(select_color_loc.as_ref(), &select_color);
 
-        // Time
-        let now = self.performance.now();
+        // Material
+        let ambient_loc = program.get_uniform_loc("material.ambient");
+        self.gl.uniform1f(ambient_loc.as_ref(), material.ambient);
+        let diffuse_loc = program.get_uniform_loc("material.diffuse");
+        self.gl.uniform1f(diffuse_loc.as_ref(), material.diffuse);
+        let specular_loc = program.get_uniform_loc("material.specular");
+        self.gl.uniform1f(specular_loc.as_ref(), material.specular);
+    }
 
-        let mut transform = Isometry3::<f32>::identity();
-        let rotation =
-            UnitQuaternion::<f32>::from_axis_angle(&Vector3::z_axis(), now as f32 / 4096.0);
-        transform.append_rotation_mut(&rotation);
-        let rotation =
-            UnitQuaternion::<f32>::from_axis_angle(&Vector3::y_axis(), now as f32 / 4096.0);
-        transform.append_rotation_mut(&rotation);
+    /// Draws nodes in batches of instances, one draw call for each batch, while the
+    /// selected node is drawn on its own so that it can be highlighted.
+    /// It leaves the default pipeline bound.
+    fn draw_instanced(&self, instancing: &Instancing, draws: &[(&Node, Isometry3<f32>)]) {
+        let selected = self.mouse.borrow().selected_node;
+        let (highlighted, batched): (Vec<_>, Vec<_>) = draws
+            .iter()
+            .partition(|(node, _)| Some(node.id) == selected);
+
+        let pipeline = &self.instanced_pipeline;
+        pipeline.pipeline.program.bind();
+        self.set_frame_uniforms(&pipeline.pipeline.program);
+
+        let keys: Vec<_> = batched
+            .iter()
+            .map(|(node, _)| (node.kind, node.material))
+            .collect();
+
+        for batch in batch_instances(&keys) {
+            let (first, _) = batched[batch[0]];
+
+            let transforms: Vec<f32> = batch
+                .iter()
+                .flat_map(|&i| batched[i].1.to_homogeneous().as_slice().to_vec())
+                .collect();
+
+            first.primitive.bind();
+            pipeline.pipeline.bind_attribs();
+            pipeline.bind_transforms(instancing, &transforms);
+            self.set_node_uniforms(&pipeline.pipeline.program, &first.material, false);
+
+            instancing.draw(&first.primitive, batch.len() as i32);
+        }
 
-        // Draw all nodes
-        for node in &self.nodes {
-            self.draw_node(now as f32, &node, &transform);
+        pipeline.unbind_transforms(instancing);
+
+        self.default_pipeline.program.bind();
+        self.set_frame_uniforms(&self.default_pipeline.program);
+        for (node, transform) in highlighted {
+            self.draw_single_node(node, &transform);
         }
+    }
 
-        Ok(())
+    fn draw_node(&self, node: &Node, parent_trs: &Isometry3<f32>, spin: &Isometry3<f32>) {
+        let transform = parent_trs * node.transform;
+        self.draw_single_node(node, &spin_transform(node, &transform, spin));
+
+        for child in &node.children {
+            self.draw_node(child, &transform, spin);
+        }
     }
 
-    fn draw_node(&self, now: f32, node: &Node, parent_trs: &Isometry3<f32>) {
+    /// Draws a node, without its children, with its final transform
+    fn draw_single_node(&self, node: &Node, transform: &Isometry3<f32>) {
         node.primitive.bind();
         self.default_pipeline.bind_attribs();
 
-        // Select color
-        let select_color_loc = self
-            .default_pipeline
-            .program
-            .get_uniform_loc("select_color");
-        let select_color = match self.mouse.borrow().selected_node {
-            Some(node_id) if node_id == node.id => [0.4f32, 0.4, 0.1, 0.0],
-            _ => [0.0f32, 0.0, 0.0, 0.0],
-        };
-        self.gl
-            .uniform4fv_with_f32_array(select_color_loc.as_ref(), &select_color);
-
-        let transform = parent_trs * node.transform;
+        let selected = self.mouse.borrow().selected_node == Some(node.id);
+        self.set_node_uniforms(&self.default_pipeline.program, &node.material, selected);
 
         self.gl.uniform_matrix4fv_with_f32_array(
             self.default_pipeline.transform_loc.as_ref(),
@@ -1086,10 +2173,6 @@ impl Context {
         );
 
         node.primitive.draw();
-
-        for child in &node.children {
-            self.draw_node(now, child, &transform);
-        }
     }
 
     /// Draw the scene with the select pipeline
@@ -1108,26 +2191,12 @@ impl Context {
 
         // Proj
         let proj_loc = self.select_pipeline.program.get_uniform_loc("proj");
-
-        let width = self.canvas.width() as f32;
-        let height = self.canvas.height() as f32;
-        let proj = nalgebra::Perspective3::new(width / height, 3.14 / 4.0, 0.125, 256.0);
-        self.gl.uniform_matrix4fv_with_f32_array(
-            proj_loc.as_ref(),
-            false,
-            proj.to_homogeneous().as_slice(),
-        );
+        self.gl
+            .uniform_matrix4fv_with_f32_array(proj_loc.as_ref(), false, self.proj().as_slice());
 
         // Time
         let now = self.performance.now();
-
-        let mut transform = Isometry3::<f32>::identity();
-        let rotation =
-            UnitQuaternion::<f32>::from_axis_angle(&Vector3::z_axis(), now as f32 / 4096.0);
-        transform.append_rotation_mut(&rotation);
-        let rotation =
-            UnitQuaternion::<f32>::from_axis_angle(&Vector3::y_axis(), now as f32 / 4096.0);
-        transform.append_rotation_mut(&rotation);
+        let spin = auto_spin(now as f32, &self.spin_pivot);
 
         // Clear framebuffer
         self.gl.clear_color(0.0, 0.0, 0.0, 1.0);
@@ -1136,13 +2205,13 @@ impl Context {
 
         // Draw all nodes
         for node in &self.nodes {
-            self.draw_select_node(now as f32, &node, &transform);
+            self.draw_select_node(node, &Isometry3::identity(), &spin);
         }
 
         Ok(())
     }
 
-    fn draw_select_node(&self, now: f32, node: &Node, parent_trs: &Isometry3<f32>) {
+    fn draw_select_node(&self, node: &Node, parent_trs: &Isometry3<f32>, spin: &Isometry3<f32>) {
         node.primitive.bind();
         self.select_pipeline.bind_attribs();
 
@@ -1166,7 +2235,9 @@ impl Context {
         self.gl.uniform_matrix4fv_with_f32_array(
             self.select_pipeline.transform_loc.as_ref(),
             false,
-            transform.to_homogeneous().as_slice(),
+            spin_transform(node, &transform, spin)
+                .to_homogeneous()
+                .as_slice(),
         );
 
         // Draw call
@@ -1174,7 +2245,218 @@ impl Context {
 
         // Recursively draw this node's children
         for child in &node.children {
-            self.draw_select_node(now, child, &transform);
+            self.draw_select_node(child, &transform, spin);
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn grid_geometry() {
+        let grid = Geometry::grid(2, 3);
+
+        // Two vertices for each of the 3 lines along X and the 4 lines along Z
+        assert_eq!(grid.vertices.len(), 14);
+        assert_eq!(grid.indices.len(), 14);
+
+        // The grid lies on the XZ plane
+        assert!(grid.vertices.iter().all(|v| v.position[1] == 0.0));
+        assert_eq!(grid.vertices[0].position, [-1.5, 0.0, -1.0]);
+        assert_eq!(grid.vertices[13].position, [1.5, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn point_stride() {
+        // Positions are interleaved xy pairs
+        let positions = vec![0.5; 2000];
+        assert_eq!(point_count(&positions), Ok(1000));
+
+        assert!(point_count(&positions[..3]).is_err());
+        assert_eq!(point_count(&[]), Ok(0));
+    }
+
+    #[test]
+    fn context_builder() {
+        let options = ContextBuilder::new();
+        assert_eq!(options.canvas_id, DEFAULT_CANVAS_ID);
+        assert!(options.antialias);
+
+        let options = options.canvas_id("preview").antialias(false);
+        assert_eq!(options.canvas_id, "preview");
+        assert!(!options.antialias);
+    }
+
+    #[test]
+    fn spin_around_pivot() {
+        let pivot = Point3::new(1.5, 0.0, 0.0);
+        let spin = auto_spin(2048.0, &pivot);
+
+        // The pivot stays in place while everything else orbits around it
+        assert!((spin * pivot - pivot).norm() < 1e-5);
+        let origin = spin * Point3::origin();
+        assert!(((origin - pivot).norm() - 1.5).abs() < 1e-5);
+        assert!((origin - Point3::origin()).norm() > 0.1);
+
+        // Spinning around the origin matches the previous scene rotation
+        let mut expected = Isometry3::identity();
+        expected.append_rotation_mut(&UnitQuaternion::from_axis_angle(&Vector3::z_axis(), 0.5));
+        expected.append_rotation_mut(&UnitQuaternion::from_axis_angle(&Vector3::y_axis(), 0.5));
+        let spin = auto_spin(2048.0, &Point3::origin());
+        assert!((spin.to_homogeneous() - expected.to_homogeneous()).norm() < 1e-5);
+    }
+
+    #[test]
+    fn reset_moved_view() {
+        let view = Rc::new(RefCell::new(initial_view()));
+        let original = view.borrow().to_homogeneous();
+
+        // Pan and orbit like the mouse handlers do
+        view.borrow_mut()
+            .append_translation_mut(&Translation3::new(0.5, -0.25, 0.0));
+        view.borrow_mut()
+            .append_rotation_wrt_center_mut(&UnitQuaternion::from_axis_angle(
+                &Vector3::y_axis(),
+                0.75,
+            ));
+        assert_ne!(view.borrow().to_homogeneous(), original);
+
+        reset_view(&view);
+        assert_eq!(view.borrow().to_homogeneous(), original);
+    }
+
+    #[test]
+    fn key_movement() {
+        // Camera at z = 12 looking at the origin, rotated half a turn around Y
+        let mut view = Isometry3::look_at_rh(
+            &Point3::new(0.0, 0.0, 12.0),
+            &Point3::origin(),
+            &Vector3::y_axis(),
+        );
+        view.append_rotation_wrt_center_mut(&UnitQuaternion::from_axis_angle(
+            &Vector3::y_axis(),
+            std::f32::consts::PI,
+        ));
+        let eye = |view: &Isometry3<f32>| view.inverse_transform_point(&Point3::origin());
+        let before = eye(&view);
+
+        // Forward moves towards the origin whichever way the camera faces
+        view.append_translation_mut(&key_translation("w", 2.0).unwrap());
+        let after = eye(&view);
+        assert!((after.coords.norm() - (before.coords.norm() - 2.0)).abs() < 1e-4);
+
+        // Up moves along the camera up axis
+        view.append_translation_mut(&key_translation("Q", 1.0).unwrap());
+        assert!((eye(&view).y - after.y - 1.0).abs() < 1e-4);
+
+        assert!(key_translation("x", 1.0).is_none());
+    }
+
+    #[test]
+    fn instance_batches() {
+        // A large grid of identical cubes takes a single draw call
+        let cube = (PrimitiveKind::Cube, Material::default());
+        let mut keys = vec![cube; 1024];
+        assert_eq!(batch_instances(&keys), vec![(0..1024).collect::<Vec<_>>()]);
+
+        // Different materials or primitives need their own draw calls
+        let shiny = Material {
+            specular: 2.0,
+            ..Material::default()
+        };
+        keys[1] = (PrimitiveKind::Cube, shiny);
+        keys[2] = (PrimitiveKind::Quad, Material::default());
+        keys[3] = (PrimitiveKind::Cube, shiny);
+
+        let batches = batch_instances(&keys);
+        assert_eq!(batches.len(), 3);
+        assert_eq!(batches[0].len(), 1021);
+        assert_eq!(batches[1], vec![1, 3]);
+        assert_eq!(batches[2], vec![2]);
+    }
+
+    #[test]
+    fn back_to_front_order() {
+        // Two overlapping quads, one in front of the other along Z
+        let front = Isometry3::translation(0.0, 0.0, 1.0);
+        let back = Isometry3::translation(0.25, 0.25, -1.0);
+        let transforms = [front, back];
+
+        let view = Isometry3::look_at_rh(
+            &Point3::new(0.0, 0.0, 12.0),
+            &Point3::origin(),
+            &Vector3::y_axis(),
+        );
+        assert_eq!(back_to_front(&view, &transforms), vec![1, 0]);
+
+        // Moving the camera behind the quads swaps the order
+        let view = Isometry3::look_at_rh(
+            &Point3::new(0.0, 0.0, -12.0),
+            &Point3::origin(),
+            &Vector3::y_axis(),
+        );
+        assert_eq!(back_to_front(&view, &transforms), vec![0, 1]);
+    }
+
+    #[test]
+    fn lens_validation() {
+        let mut lens = Lens::default();
+
+        assert!(lens.set_near(0.0).is_err());
+        assert!(lens.set_near(300.0).is_err());
+        assert!(lens.set_far(0.1).is_err());
+        assert!(lens.set_fov(0.0).is_err());
+        assert_eq!(lens, Lens::default());
+
+        lens.set_near(1.0).unwrap();
+        lens.set_far(10.0).unwrap();
+        lens.set_fov(1.0).unwrap();
+        assert_eq!(
+            lens,
+            Lens {
+                fov: 1.0,
+                near: 1.0,
+                far: 10.0
+            }
+        );
+
+        // A point on the near plane ends up at the near end of the depth range
+        let proj = projection(1.0, false, &lens);
+        let clip = proj * Point3::new(0.0, 0.0, -1.0).to_homogeneous();
+        assert!((clip.z / clip.w + 1.0).abs() < 1e-6);
+    }
+
+    /// Projects a view-space point to normalized device coordinates
+    fn to_ndc(proj: &Matrix4<f32>, point: Point3<f32>) -> (f32, f32) {
+        let clip = proj * point.to_homogeneous();
+        (clip.x / clip.w, clip.y / clip.w)
+    }
+
+    #[test]
+    fn orthographic_projection() {
+        // Two parallel cube edges going away from the camera
+        let right_edge = [Point3::new(1.0, 1.0, -4.0), Point3::new(1.0, 1.0, -6.0)];
+        let left_edge = [Point3::new(-1.0, 1.0, -4.0), Point3::new(-1.0, 1.0, -6.0)];
+
+        let direction = |proj: &Matrix4<f32>, edge: &[Point3<f32>; 2]| {
+            let (x0, y0) = to_ndc(proj, edge[0]);
+            let (x1, y1) = to_ndc(proj, edge[1]);
+            (x1 - x0, y1 - y0)
+        };
+
+        // Edges stay parallel, collapsing to a point when looked at head-on
+        let ortho = projection(4.0 / 3.0, true, &Lens::default());
+        let (dx0, dy0) = direction(&ortho, &right_edge);
+        let (dx1, dy1) = direction(&ortho, &left_edge);
+        assert!((dx0 * dy1 - dy0 * dx1).abs() < f32::EPSILON);
+        assert!(dx0.abs() < f32::EPSILON && dy0.abs() < f32::EPSILON);
+
+        // With perspective they converge towards the center of the screen
+        let persp = projection(4.0 / 3.0, false, &Lens::default());
+        let (dx0, dy0) = direction(&persp, &right_edge);
+        let (dx1, dy1) = direction(&persp, &left_edge);
+        assert!((dx0 * dy1 - dy0 * dx1).abs() > f32::EPSILON);
+    }
+}
@@ -0,0 +1,151 @@
+use nalgebra::{Isometry3, Quaternion, Translation3, UnitQuaternion};
+use web_sys::WebGlRenderingContext as GL;
+
+use crate::{Material, Node, PrimitiveKind};
+
+/// Logical data of a node which can be saved and loaded,
+/// GPU buffers are created anew when converting it back into a node
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneNode {
+    id: u32,
+    translation: [f32; 3],
+    /// Unit quaternion as `[i, j, k, w]`
+    rotation: [f32; 4],
+    kind: PrimitiveKind,
+    material: Material,
+    /// Scenes saved before nodes could stop spinning follow the automatic rotation
+    #[serde(default = "spin_by_default")]
+    spin: bool,
+    children: Vec<SceneNode>,
+}
+
+fn spin_by_default() -> bool {
+    true
+}
+
+impl SceneNode {
+    fn new(id: u32, transform: &Isometry3<f32>, kind: PrimitiveKind, material: Material) -> Self {
+        let translation = transform.translation.vector;
+        let rotation = transform.rotation.coords;
+
+        Self {
+            id,
+            translation: [translation.x, translation.y, translation.z],
+            rotation: [rotation.x, rotation.y, rotation.z, rotation.w],
+            kind,
+            material,
+            spin: true,
+            children: vec![],
+        }
+    }
+
+    fn transform(&self) -> Isometry3<f32> {
+        let [x, y, z] = self.translation;
+        let [i, j, k, w] = self.rotation;
+        Isometry3::from_parts(
+            Translation3::new(x, y, z),
+            UnitQuaternion::from_quaternion(Quaternion::new(w, i, j, k)),
+        )
+    }
+
+    /// Checks that this node and its children can be turned into nodes
+    fn validate(&self) -> Result<(), String> {
+        self.kind.validate()?;
+        self.children.iter().try_for_each(SceneNode::validate)
+    }
+
+    /// Rebuilds the node hierarchy creating new GPU primitives
+    pub fn into_node(self, gl: &GL) -> Node {
+        let mut node = Node::new(gl.clone(), self.kind);
+        node.id = self.id;
+        node.transform = self.transform();
+        node.material = self.material;
+        node.spin = self.spin;
+        node.children = self
+            .children
+            .into_iter()
+            .map(|child| child.into_node(gl))
+            .collect();
+        node
+    }
+}
+
+/// Parses a scene exported as JSON, rejecting nodes which could not be created
+pub fn parse_scene(json: &str) -> Result<Vec<SceneNode>, String> {
+    let scene: Vec<SceneNode> = serde_json::from_str(json).map_err(|err| err.to_string())?;
+    scene.iter().try_for_each(SceneNode::validate)?;
+    Ok(scene)
+}
+
+impl From<&Node> for SceneNode {
+    fn from(node: &Node) -> Self {
+        let mut scene_node = SceneNode::new(node.id, &node.transform, node.kind, node.material);
+        scene_node.spin = node.spin;
+        scene_node.children = node.children.iter().map(SceneNode::from).collect();
+        scene_node
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use nalgebra::Vector3;
+
+    #[test]
+    fn round_trip() {
+        let mut transform = Isometry3::translation(1.5, -2.0, 0.25);
+        transform.append_rotation_mut(&UnitQuaternion::from_axis_angle(&Vector3::y_axis(), 0.5));
+
+        let mut root = SceneNode::new(0, &transform, PrimitiveKind::Cube, Material::default());
+        let material = Material {
+            ambient: 0.2,
+            diffuse: 0.5,
+            specular: 0.0,
+        };
+        let mut child = SceneNode::new(
+            1,
+            &Isometry3::identity(),
+            PrimitiveKind::Grid { rows: 2, cols: 3 },
+            material,
+        );
+        child.spin = false;
+        root.children.push(child);
+
+        let json = serde_json::to_string(&vec![root.clone()]).unwrap();
+        let scene: Vec<SceneNode> = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(scene, vec![root]);
+        assert!(scene[0].transform().eq(&transform));
+    }
+
+    #[test]
+    fn spin_defaults_to_enabled() {
+        let json = r#"[{"id":0,"translation":[0,0,0],"rotation":[0,0,0,1],"kind":"Cube",
+            "material":{"ambient":0.1,"diffuse":1.0,"specular":1.0},"children":[]}]"#;
+        let scene: Vec<SceneNode> = serde_json::from_str(json).unwrap();
+        assert!(scene[0].spin);
+    }
+
+    #[test]
+    fn reject_oversized_grid() {
+        let grid = |rows, cols| {
+            SceneNode::new(
+                0,
+                &Isometry3::identity(),
+                PrimitiveKind::Grid { rows, cols },
+                Material::default(),
+            )
+        };
+        let parse = |root: SceneNode| parse_scene(&serde_json::to_string(&vec![root]).unwrap());
+
+        // 256 vertices fit 8-bit indices
+        assert!(parse(grid(63, 63)).is_ok());
+        assert!(parse(grid(200, 200)).is_err());
+        assert!(parse(grid(u32::MAX, u32::MAX)).is_err());
+
+        // Children are checked too
+        let mut root = grid(16, 16);
+        root.children.push(grid(16, 200));
+        assert!(parse(root).is_err());
+    }
+}
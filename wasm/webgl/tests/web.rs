@@ -3,6 +3,7 @@
 #![cfg(target_arch = "wasm32")]
 
 extern crate wasm_bindgen_test;
+use wasm_bindgen::JsCast;
 use wasm_bindgen_test::*;
 
 wasm_bindgen_test_configure!(run_in_browser);
@@ -11,3 +12,26 @@ wasm_bindgen_test_configure!(run_in_browser);
 fn pass() {
     assert_eq!(1 + 1, 2);
 }
+
+#[wasm_bindgen_test]
+fn missing_canvas_id() {
+    let err = webgl::Context::new_with_canvas_id("missing").err();
+    let message = err
+        .and_then(|err| err.dyn_into::<js_sys::Error>().ok())
+        .map(|err| String::from(err.message()));
+    assert_eq!(message.as_deref(), Some("canvas #missing not found"));
+}
+
+#[wasm_bindgen_test]
+fn context_with_attributes() {
+    let document = web_sys::window().unwrap().document().unwrap();
+    let canvas = document.create_element("canvas").unwrap();
+    canvas.set_id("attributes");
+    document.body().unwrap().append_child(&canvas).unwrap();
+
+    let context = webgl::ContextBuilder::new()
+        .canvas_id("attributes")
+        .antialias(false)
+        .build();
+    assert!(context.is_ok());
+}